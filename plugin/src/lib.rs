@@ -0,0 +1,672 @@
+//! the MF-102 ring modulator as a CLAP plugin
+//!
+//! a stereo effect with the knobs and switches as host automatable parameters, its state is
+//! stored as an mf-102 preset
+
+mod params;
+
+use clap_sys::entry::clap_plugin_entry;
+use clap_sys::events::{
+    clap_event_header, clap_event_param_value, clap_input_events, clap_output_events,
+    CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_PARAM_VALUE, CLAP_TRANSPORT_HAS_BEATS_TIMELINE,
+    CLAP_TRANSPORT_HAS_TEMPO, CLAP_TRANSPORT_IS_PLAYING,
+};
+use clap_sys::ext::audio_ports::{
+    clap_audio_port_info, clap_plugin_audio_ports, CLAP_AUDIO_PORT_IS_MAIN, CLAP_EXT_AUDIO_PORTS,
+    CLAP_PORT_STEREO,
+};
+use clap_sys::ext::latency::{clap_host_latency, clap_plugin_latency, CLAP_EXT_LATENCY};
+use clap_sys::ext::params::{
+    clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS, CLAP_PARAM_IS_AUTOMATABLE,
+    CLAP_PARAM_IS_ENUM, CLAP_PARAM_IS_STEPPED,
+};
+use clap_sys::ext::state::{clap_plugin_state, CLAP_EXT_STATE};
+use clap_sys::factory::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID};
+use clap_sys::fixedpoint::CLAP_BEATTIME_FACTOR;
+use clap_sys::host::clap_host;
+use clap_sys::id::clap_id;
+use clap_sys::plugin::{clap_plugin, clap_plugin_descriptor};
+use clap_sys::plugin_features::{CLAP_PLUGIN_FEATURE_AUDIO_EFFECT, CLAP_PLUGIN_FEATURE_STEREO};
+use clap_sys::process::{
+    clap_process, clap_process_status, CLAP_PROCESS_CONTINUE, CLAP_PROCESS_ERROR,
+};
+use clap_sys::stream::{clap_istream, clap_ostream};
+use clap_sys::version::CLAP_VERSION;
+use mf102::preset::Preset;
+use mf102::{RingModParams, RingModulator};
+use params::{Param, SharedValues, Values, PARAMS};
+use serde_json::{json, Value};
+use std::cell::{Cell, UnsafeCell};
+use std::ffi::{c_char, c_void, CStr};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// the plugin only has a stereo input and output
+const CHANNELS: usize = 2;
+
+/// the key of the parameters' exact values in the state, next to the preset
+const STATE_VALUES: &str = "plugin_values";
+
+struct Features([*const c_char; 4]);
+
+// only ever points at string literals
+unsafe impl Sync for Features {}
+
+static FEATURES: Features = Features([
+    CLAP_PLUGIN_FEATURE_AUDIO_EFFECT.as_ptr(),
+    CLAP_PLUGIN_FEATURE_STEREO.as_ptr(),
+    c"ring-modulator".as_ptr(),
+    ptr::null(),
+]);
+
+static DESCRIPTOR: clap_plugin_descriptor = clap_plugin_descriptor {
+    clap_version: CLAP_VERSION,
+    id: c"com.ozpv.mf-102".as_ptr(),
+    name: c"MF-102".as_ptr(),
+    vendor: c"ozpv".as_ptr(),
+    url: c"https://github.com/ozpv/mf-102".as_ptr(),
+    manual_url: c"".as_ptr(),
+    support_url: c"".as_ptr(),
+    version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+    description: c"Moogerfooger MF-102 ring modulator".as_ptr(),
+    features: FEATURES.0.as_ptr(),
+};
+
+/// what the audio thread owns between `activate` and `deactivate`
+struct Audio {
+    modulator: RingModulator,
+    /// interleaved input and output of the engine
+    input: Vec<f32>,
+    output: Vec<f32>,
+}
+
+struct Plugin {
+    clap: clap_plugin,
+    host: *const clap_host,
+    /// the host's latency extension, null if it has none, only touched by the main thread
+    host_latency: Cell<*const clap_host_latency>,
+    values: SharedValues,
+    /// only touched by the main thread while deactivated and by the audio thread while activated
+    audio: UnsafeCell<Option<Audio>>,
+    /// the latency reported to the host, only changes while deactivated
+    latency: AtomicU32,
+    restart_requested: AtomicBool,
+}
+
+impl Plugin {
+    /// # Safety
+    ///
+    /// `plugin` has to be created by [`factory_create_plugin`] and not yet destroyed
+    unsafe fn from_raw<'a>(plugin: *const clap_plugin) -> &'a Self {
+        &*((*plugin).plugin_data as *const Self)
+    }
+
+    /// # Safety
+    ///
+    /// only on the audio thread while activated or on the main thread while deactivated
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn audio(&self) -> &mut Option<Audio> {
+        &mut *self.audio.get()
+    }
+
+    /// applies the parameter changes among `events` up to and including `until` frames,
+    /// starting at `*next`, returns the time of the next one
+    unsafe fn apply_events(
+        &self,
+        events: &clap_input_events,
+        next: &mut u32,
+        until: u32,
+        values: &mut Values,
+    ) -> Option<u32> {
+        let count = events.size.map_or(0, |size| size(events));
+
+        while *next < count {
+            let Some(header) = events.get.map(|get| get(events, *next)) else {
+                break;
+            };
+
+            if header.is_null() {
+                *next += 1;
+                continue;
+            }
+
+            if (*header).time > until {
+                return Some((*header).time);
+            }
+
+            self.apply_event(header, values);
+            *next += 1;
+        }
+
+        None
+    }
+
+    unsafe fn apply_event(&self, header: *const clap_event_header, values: &mut Values) {
+        if (*header).space_id != CLAP_CORE_EVENT_SPACE_ID
+            || (*header).type_ != CLAP_EVENT_PARAM_VALUE
+        {
+            return;
+        }
+
+        let event = &*(header as *const clap_event_param_value);
+
+        if let Some(param) = Param::from_id(event.param_id) {
+            let value = param.clamp(event.value);
+            values[param as usize] = value;
+            self.values.set(param, value);
+        }
+    }
+}
+
+unsafe extern "C" fn init(plugin: *const clap_plugin) -> bool {
+    let plugin = Plugin::from_raw(plugin);
+
+    if let Some(get_extension) = (*plugin.host).get_extension {
+        let host_latency = get_extension(plugin.host, CLAP_EXT_LATENCY.as_ptr());
+        plugin
+            .host_latency
+            .set(host_latency as *const clap_host_latency);
+    }
+
+    true
+}
+
+unsafe extern "C" fn destroy(plugin: *const clap_plugin) {
+    drop(Box::from_raw((*plugin).plugin_data as *mut Plugin));
+}
+
+unsafe extern "C" fn activate(
+    plugin: *const clap_plugin,
+    sample_rate: f64,
+    _min_frames_count: u32,
+    max_frames_count: u32,
+) -> bool {
+    let plugin = Plugin::from_raw(plugin);
+    let params = params::to_params(&plugin.values.load());
+    // the drive is automatable, so the input stage is always in the signal path
+    let modulator = RingModulator::with_drive_stage(params, sample_rate.round() as u32, CHANNELS);
+    let buffer = vec![0.0; max_frames_count as usize * CHANNELS];
+
+    let latency = modulator.latency().round() as u32;
+
+    // the first activation, or a restart for a changed oversampling, the host has to be told
+    // while activating
+    if plugin.latency.swap(latency, Ordering::Relaxed) != latency {
+        if let Some(changed) = plugin.host_latency.get().as_ref().and_then(|l| l.changed) {
+            changed(plugin.host);
+        }
+    }
+
+    plugin.restart_requested.store(false, Ordering::Relaxed);
+
+    *plugin.audio() = Some(Audio {
+        modulator,
+        input: buffer.clone(),
+        output: buffer,
+    });
+
+    true
+}
+
+unsafe extern "C" fn deactivate(plugin: *const clap_plugin) {
+    *Plugin::from_raw(plugin).audio() = None;
+}
+
+unsafe extern "C" fn start_processing(_plugin: *const clap_plugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn stop_processing(_plugin: *const clap_plugin) {}
+
+unsafe extern "C" fn reset(plugin: *const clap_plugin) {
+    if let Some(audio) = Plugin::from_raw(plugin).audio() {
+        audio.modulator.reset();
+    }
+}
+
+unsafe extern "C" fn process(
+    plugin: *const clap_plugin,
+    process: *const clap_process,
+) -> clap_process_status {
+    let plugin = Plugin::from_raw(plugin);
+    let process = &*process;
+
+    let Some(audio) = plugin.audio() else {
+        return CLAP_PROCESS_ERROR;
+    };
+
+    if process.audio_inputs_count == 0 || process.audio_outputs_count == 0 {
+        return CLAP_PROCESS_ERROR;
+    }
+
+    let input = &*process.audio_inputs;
+    let output = &*process.audio_outputs;
+    let frames = process.frames_count;
+
+    if input.data32.is_null()
+        || output.data32.is_null()
+        || input.channel_count == 0
+        || output.channel_count == 0
+        || frames as usize * CHANNELS > audio.input.len()
+    {
+        return CLAP_PROCESS_ERROR;
+    }
+
+    // a synced LFO follows the host's tempo, and its song position while it's playing
+    if let Some(transport) = process.transport.as_ref() {
+        if transport.flags & CLAP_TRANSPORT_HAS_TEMPO != 0 {
+            audio.modulator.set_tempo(transport.tempo);
+        }
+
+        let playing = CLAP_TRANSPORT_HAS_BEATS_TIMELINE | CLAP_TRANSPORT_IS_PLAYING;
+
+        if transport.flags & playing == playing {
+            let beats = transport.song_pos_beats as f64 / CLAP_BEATTIME_FACTOR as f64;
+            audio.modulator.set_song_position(beats);
+        }
+    }
+
+    let mut values = plugin.values.load();
+    let mut next_event = 0;
+    let mut start = 0;
+
+    // split the block at every parameter change, the engine glides towards the new settings
+    while start < frames {
+        let end = match process.in_events.is_null() {
+            true => None,
+            false => plugin.apply_events(&*process.in_events, &mut next_event, start, &mut values),
+        }
+        .map_or(frames, |time| time.min(frames));
+
+        audio.modulator.set_params(params::to_params(&values));
+
+        let (from, to) = (start as usize, end as usize);
+        let len = (to - from) * CHANNELS;
+
+        for channel in 0..CHANNELS {
+            let samples = *input
+                .data32
+                .add(channel.min(input.channel_count as usize - 1));
+
+            for frame in from..to {
+                audio.input[(frame - from) * CHANNELS + channel] = *samples.add(frame);
+            }
+        }
+
+        audio
+            .modulator
+            .process_block(&audio.input[..len], &mut audio.output[..len]);
+
+        for channel in 0..CHANNELS.min(output.channel_count as usize) {
+            let samples = *output.data32.add(channel);
+
+            for frame in from..to {
+                *samples.add(frame) = audio.output[(frame - from) * CHANNELS + channel];
+            }
+        }
+
+        start = end;
+    }
+
+    // a changed oversampling takes a new modulator and with it a new latency, which the host only
+    // picks up on a restart
+    if audio.modulator.oversampling_changed()
+        && !plugin.restart_requested.swap(true, Ordering::Relaxed)
+    {
+        if let Some(request_restart) = (*plugin.host).request_restart {
+            request_restart(plugin.host);
+        }
+    }
+
+    CLAP_PROCESS_CONTINUE
+}
+
+unsafe extern "C" fn get_extension(
+    _plugin: *const clap_plugin,
+    id: *const c_char,
+) -> *const c_void {
+    let id = CStr::from_ptr(id);
+
+    if id == CLAP_EXT_AUDIO_PORTS {
+        &AUDIO_PORTS as *const _ as *const c_void
+    } else if id == CLAP_EXT_PARAMS {
+        &PARAMS_EXT as *const _ as *const c_void
+    } else if id == CLAP_EXT_STATE {
+        &STATE as *const _ as *const c_void
+    } else if id == CLAP_EXT_LATENCY {
+        &LATENCY as *const _ as *const c_void
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn on_main_thread(_plugin: *const clap_plugin) {}
+
+/// copies `s` into a C string buffer of `capacity` bytes, truncating it if needed
+unsafe fn write_str(s: &str, buffer: *mut c_char, capacity: usize) {
+    if capacity == 0 {
+        return;
+    }
+
+    let len = s.len().min(capacity - 1);
+    ptr::copy_nonoverlapping(s.as_ptr() as *const c_char, buffer, len);
+    *buffer.add(len) = 0;
+}
+
+static AUDIO_PORTS: clap_plugin_audio_ports = clap_plugin_audio_ports {
+    count: Some(audio_ports_count),
+    get: Some(audio_ports_get),
+};
+
+unsafe extern "C" fn audio_ports_count(_plugin: *const clap_plugin, _is_input: bool) -> u32 {
+    1
+}
+
+unsafe extern "C" fn audio_ports_get(
+    _plugin: *const clap_plugin,
+    index: u32,
+    is_input: bool,
+    info: *mut clap_audio_port_info,
+) -> bool {
+    if index != 0 {
+        return false;
+    }
+
+    let info = &mut *info;
+    info.id = 0;
+    write_str(
+        if is_input { "Input" } else { "Output" },
+        info.name.as_mut_ptr(),
+        info.name.len(),
+    );
+    info.flags = CLAP_AUDIO_PORT_IS_MAIN;
+    info.channel_count = CHANNELS as u32;
+    info.port_type = CLAP_PORT_STEREO.as_ptr();
+    info.in_place_pair = 0;
+
+    true
+}
+
+static PARAMS_EXT: clap_plugin_params = clap_plugin_params {
+    count: Some(params_count),
+    get_info: Some(params_get_info),
+    get_value: Some(params_get_value),
+    value_to_text: Some(params_value_to_text),
+    text_to_value: Some(params_text_to_value),
+    flush: Some(params_flush),
+};
+
+unsafe extern "C" fn params_count(_plugin: *const clap_plugin) -> u32 {
+    PARAMS.len() as u32
+}
+
+unsafe extern "C" fn params_get_info(
+    _plugin: *const clap_plugin,
+    index: u32,
+    info: *mut clap_param_info,
+) -> bool {
+    let Some(param) = Param::from_id(index) else {
+        return false;
+    };
+
+    let info = &mut *info;
+    let (min, max) = param.bounds();
+    let mut flags = CLAP_PARAM_IS_AUTOMATABLE;
+
+    if param.is_stepped() {
+        flags |= CLAP_PARAM_IS_STEPPED;
+    }
+
+    if param.is_enum() {
+        flags |= CLAP_PARAM_IS_ENUM;
+    }
+
+    info.id = param.id();
+    info.flags = flags;
+    info.cookie = ptr::null_mut();
+    write_str(param.name(), info.name.as_mut_ptr(), info.name.len());
+    write_str(param.module(), info.module.as_mut_ptr(), info.module.len());
+    info.min_value = min;
+    info.max_value = max;
+    info.default_value = params::from_params(&RingModParams::default())[param as usize];
+
+    true
+}
+
+unsafe extern "C" fn params_get_value(
+    plugin: *const clap_plugin,
+    id: clap_id,
+    value: *mut f64,
+) -> bool {
+    let Some(param) = Param::from_id(id) else {
+        return false;
+    };
+
+    *value = Plugin::from_raw(plugin).values.get(param);
+
+    true
+}
+
+unsafe extern "C" fn params_value_to_text(
+    plugin: *const clap_plugin,
+    id: clap_id,
+    value: f64,
+    buffer: *mut c_char,
+    capacity: u32,
+) -> bool {
+    let Some(param) = Param::from_id(id) else {
+        return false;
+    };
+
+    let text = param.value_to_text(value, &Plugin::from_raw(plugin).values.load());
+    write_str(&text, buffer, capacity as usize);
+
+    true
+}
+
+unsafe extern "C" fn params_text_to_value(
+    plugin: *const clap_plugin,
+    id: clap_id,
+    text: *const c_char,
+    value: *mut f64,
+) -> bool {
+    let (Some(param), Ok(text)) = (Param::from_id(id), CStr::from_ptr(text).to_str()) else {
+        return false;
+    };
+
+    match param.text_to_value(text, &Plugin::from_raw(plugin).values.load()) {
+        Some(parsed) => {
+            *value = parsed;
+            true
+        }
+        None => false,
+    }
+}
+
+unsafe extern "C" fn params_flush(
+    plugin: *const clap_plugin,
+    events: *const clap_input_events,
+    _out: *const clap_output_events,
+) {
+    let plugin = Plugin::from_raw(plugin);
+    let mut values = plugin.values.load();
+
+    if !events.is_null() {
+        plugin.apply_events(&*events, &mut 0, u32::MAX, &mut values);
+    }
+}
+
+static STATE: clap_plugin_state = clap_plugin_state {
+    save: Some(state_save),
+    load: Some(state_load),
+};
+
+/// the preset of the current settings, with the exact parameter values so they're restored
+/// bit for bit
+unsafe extern "C" fn state_save(plugin: *const clap_plugin, stream: *const clap_ostream) -> bool {
+    let values = Plugin::from_raw(plugin).values.load();
+    let preset = Preset::new(params::to_params(&values), None);
+
+    let Ok(Value::Object(mut state)) = serde_json::from_str::<Value>(&preset.to_json()) else {
+        return false;
+    };
+    state.insert(STATE_VALUES.to_string(), json!(values));
+
+    let state = Value::Object(state).to_string();
+    let Some(write) = (*stream).write else {
+        return false;
+    };
+
+    let mut written = 0;
+
+    while written < state.len() {
+        let rest = &state.as_bytes()[written..];
+        let n = write(stream, rest.as_ptr() as *const c_void, rest.len() as u64);
+
+        if n <= 0 {
+            return false;
+        }
+
+        written += n as usize;
+    }
+
+    true
+}
+
+/// restores a state or loads any preset, migrating it if it's older
+unsafe extern "C" fn state_load(plugin: *const clap_plugin, stream: *const clap_istream) -> bool {
+    let Some(read) = (*stream).read else {
+        return false;
+    };
+
+    let mut state = vec![];
+    let mut buffer = [0u8; 4096];
+
+    loop {
+        match read(
+            stream,
+            buffer.as_mut_ptr() as *mut c_void,
+            buffer.len() as u64,
+        ) {
+            0 => break,
+            n if n < 0 => return false,
+            n => state.extend_from_slice(&buffer[..n as usize]),
+        }
+    }
+
+    let Ok(state) = String::from_utf8(state) else {
+        return false;
+    };
+    let Ok(preset) = Preset::from_json(&state) else {
+        return false;
+    };
+
+    // the exact values if they're there, the preset's for parameters added since it was saved
+    let mut values = params::from_params(&preset.params);
+    let saved = serde_json::from_str::<Value>(&state)
+        .ok()
+        .and_then(|state| serde_json::from_value::<Vec<f64>>(state.get(STATE_VALUES)?.clone()).ok())
+        .filter(|saved| saved.len() <= values.len())
+        .unwrap_or_default();
+
+    for (value, (param, saved)) in values.iter_mut().zip(PARAMS.into_iter().zip(saved)) {
+        *value = param.clamp(saved);
+    }
+
+    Plugin::from_raw(plugin).values.store(&values);
+
+    true
+}
+
+static LATENCY: clap_plugin_latency = clap_plugin_latency {
+    get: Some(latency_get),
+};
+
+unsafe extern "C" fn latency_get(plugin: *const clap_plugin) -> u32 {
+    Plugin::from_raw(plugin).latency.load(Ordering::Relaxed)
+}
+
+static FACTORY: clap_plugin_factory = clap_plugin_factory {
+    get_plugin_count: Some(factory_get_plugin_count),
+    get_plugin_descriptor: Some(factory_get_plugin_descriptor),
+    create_plugin: Some(factory_create_plugin),
+};
+
+unsafe extern "C" fn factory_get_plugin_count(_factory: *const clap_plugin_factory) -> u32 {
+    1
+}
+
+unsafe extern "C" fn factory_get_plugin_descriptor(
+    _factory: *const clap_plugin_factory,
+    index: u32,
+) -> *const clap_plugin_descriptor {
+    if index == 0 {
+        &DESCRIPTOR
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn factory_create_plugin(
+    _factory: *const clap_plugin_factory,
+    host: *const clap_host,
+    plugin_id: *const c_char,
+) -> *const clap_plugin {
+    if host.is_null()
+        || plugin_id.is_null()
+        || CStr::from_ptr(plugin_id) != CStr::from_ptr(DESCRIPTOR.id)
+    {
+        return ptr::null();
+    }
+
+    let plugin = Box::into_raw(Box::new(Plugin {
+        clap: clap_plugin {
+            desc: &DESCRIPTOR,
+            plugin_data: ptr::null_mut(),
+            init: Some(init),
+            destroy: Some(destroy),
+            activate: Some(activate),
+            deactivate: Some(deactivate),
+            start_processing: Some(start_processing),
+            stop_processing: Some(stop_processing),
+            reset: Some(reset),
+            process: Some(process),
+            get_extension: Some(get_extension),
+            on_main_thread: Some(on_main_thread),
+        },
+        host,
+        host_latency: Cell::new(ptr::null()),
+        values: SharedValues::new(&params::from_params(&RingModParams::default())),
+        audio: UnsafeCell::new(None),
+        latency: AtomicU32::new(0),
+        restart_requested: AtomicBool::new(false),
+    }));
+
+    (*plugin).clap.plugin_data = plugin as *mut c_void;
+
+    &(*plugin).clap
+}
+
+unsafe extern "C" fn entry_init(_plugin_path: *const c_char) -> bool {
+    true
+}
+
+unsafe extern "C" fn entry_deinit() {}
+
+unsafe extern "C" fn entry_get_factory(factory_id: *const c_char) -> *const c_void {
+    if !factory_id.is_null() && CStr::from_ptr(factory_id) == CLAP_PLUGIN_FACTORY_ID {
+        &FACTORY as *const _ as *const c_void
+    } else {
+        ptr::null()
+    }
+}
+
+/// the symbol hosts look up in the library
+#[allow(non_upper_case_globals)]
+#[no_mangle]
+pub static clap_entry: clap_plugin_entry = clap_plugin_entry {
+    clap_version: CLAP_VERSION,
+    init: Some(entry_init),
+    deinit: Some(entry_deinit),
+    get_factory: Some(entry_get_factory),
+};
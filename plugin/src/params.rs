@@ -0,0 +1,522 @@
+//! the knobs and switches as host automatable parameters
+
+use mf102::{
+    CarrierWaveform, Feel, FrequencyRange, LfoModulation, LfoSync, Multiplier, NoteDivision,
+    RingModParams, Waveform,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// a parameter, its id is its position in [`PARAMS`] so new ones only ever go at the end
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    Mix,
+    /// the 0 to 10 dial position, mapped onto the range like the hardware's
+    Frequency,
+    Range,
+    Amount,
+    Rate,
+    Waveform,
+    Drive,
+    ChannelPhaseOffset,
+    /// an index into [`SYNC_DIVISIONS`]
+    Sync,
+    Multiplier,
+    CarrierBleed,
+    SignalFeedthrough,
+    Nonlinearity,
+    Vintage,
+    /// every unit the command line takes
+    Unit,
+    Drift,
+    PulseWidth,
+    CarrierWaveform,
+    CarrierPulseWidth,
+    LfoSlew,
+    LfoModulation,
+    /// an index into [`OVERSAMPLING`]
+    Oversampling,
+}
+
+/// every parameter in the order of their ids
+pub const PARAMS: [Param; 22] = [
+    Param::Mix,
+    Param::Frequency,
+    Param::Range,
+    Param::Amount,
+    Param::Rate,
+    Param::Waveform,
+    Param::Drive,
+    Param::ChannelPhaseOffset,
+    Param::Sync,
+    Param::Multiplier,
+    Param::CarrierBleed,
+    Param::SignalFeedthrough,
+    Param::Nonlinearity,
+    Param::Vintage,
+    Param::Unit,
+    Param::Drift,
+    Param::PulseWidth,
+    Param::CarrierWaveform,
+    Param::CarrierPulseWidth,
+    Param::LfoSlew,
+    Param::LfoModulation,
+    Param::Oversampling,
+];
+
+/// the factors the oversampling parameter steps through
+pub const OVERSAMPLING: [u32; 4] = [1, 2, 4, 8];
+
+/// the LFO speeds the sync parameter steps through, from slowest to fastest
+pub const SYNC_DIVISIONS: [LfoSync; 17] = [
+    LfoSync::Free,
+    division(4, 1, Feel::Straight),
+    division(2, 1, Feel::Straight),
+    division(1, 1, Feel::Straight),
+    division(1, 2, Feel::Dotted),
+    division(1, 2, Feel::Straight),
+    division(1, 2, Feel::Triplet),
+    division(1, 4, Feel::Dotted),
+    division(1, 4, Feel::Straight),
+    division(1, 4, Feel::Triplet),
+    division(1, 8, Feel::Dotted),
+    division(1, 8, Feel::Straight),
+    division(1, 8, Feel::Triplet),
+    division(1, 16, Feel::Dotted),
+    division(1, 16, Feel::Straight),
+    division(1, 16, Feel::Triplet),
+    division(1, 32, Feel::Straight),
+];
+
+const fn division(count: u16, note: u16, feel: Feel) -> LfoSync {
+    LfoSync::Tempo(NoteDivision { count, note, feel })
+}
+
+/// the value of every parameter, indexed by id
+pub type Values = [f64; PARAMS.len()];
+
+impl Param {
+    pub fn from_id(id: u32) -> Option<Self> {
+        PARAMS.get(id as usize).copied()
+    }
+
+    pub fn id(self) -> u32 {
+        self as u32
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mix => "Mix",
+            Self::Frequency => "Frequency",
+            Self::Range => "Range",
+            Self::Amount => "Amount",
+            Self::Rate => "Rate",
+            Self::Waveform => "LFO Waveform",
+            Self::Drive => "Drive",
+            Self::ChannelPhaseOffset => "Stereo Phase",
+            Self::Sync => "LFO Sync",
+            Self::Multiplier => "Multiplier",
+            Self::CarrierBleed => "Carrier Bleed",
+            Self::SignalFeedthrough => "Signal Feedthrough",
+            Self::Nonlinearity => "Nonlinearity",
+            Self::Vintage => "Vintage",
+            Self::Unit => "Unit",
+            Self::Drift => "Drift",
+            Self::PulseWidth => "Pulse Width",
+            Self::CarrierWaveform => "Carrier Waveform",
+            Self::CarrierPulseWidth => "Carrier Pulse Width",
+            Self::LfoSlew => "LFO Slew",
+            Self::LfoModulation => "LFO Modulation",
+            Self::Oversampling => "Oversampling",
+        }
+    }
+
+    /// the section of the front panel it's in
+    pub fn module(self) -> &'static str {
+        match self {
+            Self::Drive => "Input",
+            Self::Amount
+            | Self::Rate
+            | Self::Waveform
+            | Self::Sync
+            | Self::PulseWidth
+            | Self::LfoSlew
+            | Self::LfoModulation => "LFO",
+            Self::Mix
+            | Self::Frequency
+            | Self::Range
+            | Self::ChannelPhaseOffset
+            | Self::CarrierWaveform
+            | Self::CarrierPulseWidth => "Modulator",
+            Self::Multiplier
+            | Self::CarrierBleed
+            | Self::SignalFeedthrough
+            | Self::Nonlinearity
+            | Self::Oversampling => "Multiplier",
+            Self::Vintage | Self::Unit | Self::Drift => "Vintage",
+        }
+    }
+
+    pub fn bounds(self) -> (f64, f64) {
+        match self {
+            Self::Mix => (0.0, 100.0),
+            Self::Frequency | Self::Amount | Self::Drive => (0.0, 10.0),
+            Self::Waveform => (0.0, (Waveform::ALL.len() - 1) as f64),
+            Self::CarrierWaveform => (0.0, (CarrierWaveform::ALL.len() - 1) as f64),
+            Self::PulseWidth | Self::CarrierPulseWidth => (5.0, 95.0),
+            Self::Range
+            | Self::Multiplier
+            | Self::Nonlinearity
+            | Self::Vintage
+            | Self::LfoModulation => (0.0, 1.0),
+            Self::Unit => (0.0, u32::MAX as f64),
+            Self::Drift => (0.0, 50.0),
+            Self::LfoSlew => (0.0, 100.0),
+            Self::CarrierBleed | Self::SignalFeedthrough => (0.0, 10.0),
+            Self::Rate => (0.1, 25.0),
+            Self::ChannelPhaseOffset => (0.0, 180.0),
+            Self::Sync => (0.0, (SYNC_DIVISIONS.len() - 1) as f64),
+            Self::Oversampling => (0.0, (OVERSAMPLING.len() - 1) as f64),
+        }
+    }
+
+    /// whether it only takes whole numbers
+    pub fn is_stepped(self) -> bool {
+        matches!(
+            self,
+            Self::Mix
+                | Self::Range
+                | Self::Waveform
+                | Self::CarrierWaveform
+                | Self::Sync
+                | Self::Multiplier
+                | Self::Vintage
+                | Self::Unit
+                | Self::LfoModulation
+                | Self::Oversampling
+        )
+    }
+
+    /// whether it's a switch rather than a knob
+    pub fn is_enum(self) -> bool {
+        matches!(
+            self,
+            Self::Range
+                | Self::Waveform
+                | Self::CarrierWaveform
+                | Self::Sync
+                | Self::Multiplier
+                | Self::Vintage
+                | Self::LfoModulation
+                | Self::Oversampling
+        )
+    }
+
+    /// `value` within the bounds, rounded if it's stepped
+    pub fn clamp(self, value: f64) -> f64 {
+        let (min, max) = self.bounds();
+        let value = value.clamp(min, max);
+
+        if self.is_stepped() {
+            value.round()
+        } else {
+            value
+        }
+    }
+
+    /// `value` for display, `values` being the other parameters it depends on
+    pub fn value_to_text(self, value: f64, values: &Values) -> String {
+        match self {
+            Self::Mix | Self::PulseWidth | Self::CarrierPulseWidth => format!("{value:.0}%"),
+            Self::Frequency => {
+                let frequency = range(values[Self::Range as usize]).knob_to_frequency(value as f32);
+                format!("{frequency:.1} Hz")
+            }
+            Self::Range => match range(value) {
+                FrequencyRange::Lo => "LO".to_string(),
+                FrequencyRange::Hi => "HI".to_string(),
+            },
+            Self::Amount | Self::Drive => format!("{value:.2}"),
+            Self::Rate => format!("{value:.2} Hz"),
+            Self::Waveform => match waveform(value) {
+                Waveform::Sinusoidal => "Sine".to_string(),
+                Waveform::Square => "Square".to_string(),
+                Waveform::Triangle => "Triangle".to_string(),
+                Waveform::RampUp => "Ramp Up".to_string(),
+                Waveform::RampDown => "Ramp Down".to_string(),
+                Waveform::SampleAndHold => "Sample & Hold".to_string(),
+                Waveform::SmoothRandom => "Smooth Random".to_string(),
+            },
+            Self::CarrierWaveform => match carrier_waveform(value) {
+                CarrierWaveform::Sine => "Sine".to_string(),
+                CarrierWaveform::Triangle => "Triangle".to_string(),
+                CarrierWaveform::Square => "Square".to_string(),
+                CarrierWaveform::Saw => "Saw".to_string(),
+                CarrierWaveform::Pulse => "Pulse".to_string(),
+            },
+            Self::ChannelPhaseOffset => format!("{value:.0}°"),
+            Self::Sync => match sync(value) {
+                LfoSync::Free => "Free".to_string(),
+                LfoSync::Tempo(division) => division.to_string(),
+            },
+            Self::Multiplier => match multiplier(value) {
+                Multiplier::Ideal => "Ideal".to_string(),
+                Multiplier::Analog => "Analog".to_string(),
+            },
+            Self::CarrierBleed | Self::SignalFeedthrough => format!("{value:.2}%"),
+            Self::Nonlinearity => format!("{value:.2}"),
+            Self::Vintage => if value < 0.5 { "Off" } else { "On" }.to_string(),
+            Self::Oversampling => match oversampling(value) {
+                1 => "Off".to_string(),
+                factor => format!("{factor}x"),
+            },
+            Self::Unit => format!("{value:.0}"),
+            Self::Drift => format!("{value:.1} cents"),
+            Self::LfoSlew => format!("{value:.1} ms"),
+            Self::LfoModulation => match lfo_modulation(value) {
+                LfoModulation::Octaves => "Octaves".to_string(),
+                LfoModulation::Linear => "Linear".to_string(),
+            },
+        }
+    }
+
+    /// parses what [`value_to_text`](Self::value_to_text) displays, with or without the unit
+    pub fn text_to_value(self, text: &str, values: &Values) -> Option<f64> {
+        let text = text.trim().to_lowercase();
+        let number = || {
+            text.trim_end_matches(['%', '°'])
+                .trim_end_matches("hz")
+                .trim()
+                .parse::<f64>()
+                .ok()
+        };
+
+        let value = match self {
+            Self::Frequency => {
+                let range = range(values[Self::Range as usize]);
+                f64::from(range.frequency_to_knob(number()? as f32))
+            }
+            Self::Range => match text.parse().ok()? {
+                FrequencyRange::Lo => 0.0,
+                FrequencyRange::Hi => 1.0,
+            },
+            Self::Waveform => {
+                let waveform = text.replace(' ', "-").replace('&', "and");
+                let waveform = waveform.parse().ok()?;
+                Waveform::ALL.iter().position(|w| *w == waveform)? as f64
+            }
+            Self::CarrierWaveform => {
+                let waveform = text.parse().ok()?;
+                CarrierWaveform::ALL.iter().position(|w| *w == waveform)? as f64
+            }
+            Self::Sync => {
+                let sync = text.parse().ok()?;
+                SYNC_DIVISIONS.iter().position(|s| *s == sync)? as f64
+            }
+            Self::Multiplier => match text.parse().ok()? {
+                Multiplier::Ideal => 0.0,
+                Multiplier::Analog => 1.0,
+            },
+            Self::LfoModulation => match text.parse().ok()? {
+                LfoModulation::Octaves => 0.0,
+                LfoModulation::Linear => 1.0,
+            },
+            Self::Vintage => match text.as_str() {
+                "off" => 0.0,
+                "on" => 1.0,
+                _ => return None,
+            },
+            Self::Drift => text.trim_end_matches("cents").trim().parse().ok()?,
+            Self::Oversampling => {
+                let factor = match text.as_str() {
+                    "off" => 1,
+                    _ => text.trim_end_matches('x').trim().parse().ok()?,
+                };
+                OVERSAMPLING.iter().position(|f| *f == factor)? as f64
+            }
+            Self::LfoSlew => text.trim_end_matches("ms").trim().parse().ok()?,
+            Self::Mix
+            | Self::Amount
+            | Self::Rate
+            | Self::Drive
+            | Self::ChannelPhaseOffset
+            | Self::CarrierBleed
+            | Self::SignalFeedthrough
+            | Self::Nonlinearity
+            | Self::Unit
+            | Self::PulseWidth
+            | Self::CarrierPulseWidth => number()?,
+        };
+
+        Some(self.clamp(value))
+    }
+}
+
+fn range(value: f64) -> FrequencyRange {
+    if value < 0.5 {
+        FrequencyRange::Lo
+    } else {
+        FrequencyRange::Hi
+    }
+}
+
+fn waveform(value: f64) -> Waveform {
+    Waveform::ALL[(value.round() as usize).min(Waveform::ALL.len() - 1)]
+}
+
+fn carrier_waveform(value: f64) -> CarrierWaveform {
+    CarrierWaveform::ALL[(value.round() as usize).min(CarrierWaveform::ALL.len() - 1)]
+}
+
+fn lfo_modulation(value: f64) -> LfoModulation {
+    if value < 0.5 {
+        LfoModulation::Octaves
+    } else {
+        LfoModulation::Linear
+    }
+}
+
+fn multiplier(value: f64) -> Multiplier {
+    if value < 0.5 {
+        Multiplier::Ideal
+    } else {
+        Multiplier::Analog
+    }
+}
+
+fn oversampling(value: f64) -> u32 {
+    OVERSAMPLING[(value.round() as usize).min(OVERSAMPLING.len() - 1)]
+}
+
+fn sync(value: f64) -> LfoSync {
+    SYNC_DIVISIONS[(value.round() as usize).min(SYNC_DIVISIONS.len() - 1)]
+}
+
+/// the settings the parameters stand for
+pub fn to_params(values: &Values) -> RingModParams {
+    let value = |param: Param| values[param as usize];
+    let range = range(value(Param::Range));
+
+    RingModParams {
+        drive: value(Param::Drive) as f32,
+        amount: value(Param::Amount) as f32,
+        lfo_waveform: waveform(value(Param::Waveform)),
+        pulse_width: value(Param::PulseWidth) as f32,
+        lfo_slew: value(Param::LfoSlew) as f32,
+        lfo_modulation: lfo_modulation(value(Param::LfoModulation)),
+        rate: value(Param::Rate) as f32,
+        sync: sync(value(Param::Sync)),
+        mix: value(Param::Mix).round() as u8,
+        frequency: range.knob_to_frequency(value(Param::Frequency) as f32),
+        range,
+        channel_phase_offset: value(Param::ChannelPhaseOffset) as f32,
+        carrier_waveform: carrier_waveform(value(Param::CarrierWaveform)),
+        carrier_pulse_width: value(Param::CarrierPulseWidth) as f32,
+        multiplier: multiplier(value(Param::Multiplier)),
+        carrier_bleed: value(Param::CarrierBleed) as f32,
+        signal_feedthrough: value(Param::SignalFeedthrough) as f32,
+        nonlinearity: value(Param::Nonlinearity) as f32,
+        oversampling: oversampling(value(Param::Oversampling)),
+        vintage: value(Param::Vintage) >= 0.5,
+        unit: value(Param::Unit).round() as u32,
+        drift: value(Param::Drift) as f32,
+    }
+}
+
+/// the parameters standing for `params`
+pub fn from_params(params: &RingModParams) -> Values {
+    PARAMS.map(|param| {
+        let value = match param {
+            Param::Mix => f64::from(params.mix),
+            Param::Frequency => f64::from(params.range.frequency_to_knob(params.frequency)),
+            Param::Range => match params.range {
+                FrequencyRange::Lo => 0.0,
+                FrequencyRange::Hi => 1.0,
+            },
+            Param::Amount => f64::from(params.amount),
+            Param::Rate => f64::from(params.rate),
+            Param::Waveform => Waveform::ALL
+                .iter()
+                .position(|w| *w == params.lfo_waveform)
+                .unwrap_or(0) as f64,
+            Param::PulseWidth => f64::from(params.pulse_width),
+            Param::LfoSlew => f64::from(params.lfo_slew),
+            Param::LfoModulation => match params.lfo_modulation {
+                LfoModulation::Octaves => 0.0,
+                LfoModulation::Linear => 1.0,
+            },
+            Param::Drive => f64::from(params.drive),
+            Param::ChannelPhaseOffset => f64::from(params.channel_phase_offset),
+            Param::CarrierWaveform => CarrierWaveform::ALL
+                .iter()
+                .position(|w| *w == params.carrier_waveform)
+                .unwrap_or(0) as f64,
+            Param::CarrierPulseWidth => f64::from(params.carrier_pulse_width),
+            Param::Sync => sync_index(params.sync) as f64,
+            Param::Multiplier => match params.multiplier {
+                Multiplier::Ideal => 0.0,
+                Multiplier::Analog => 1.0,
+            },
+            Param::CarrierBleed => f64::from(params.carrier_bleed),
+            Param::SignalFeedthrough => f64::from(params.signal_feedthrough),
+            Param::Nonlinearity => f64::from(params.nonlinearity),
+            Param::Oversampling => OVERSAMPLING
+                .iter()
+                .position(|f| *f >= params.oversampling)
+                .unwrap_or(OVERSAMPLING.len() - 1) as f64,
+            Param::Vintage => f64::from(u8::from(params.vintage)),
+            Param::Unit => f64::from(params.unit),
+            Param::Drift => f64::from(params.drift),
+        };
+
+        param.clamp(value)
+    })
+}
+
+/// the division of the sync parameter `sync` is or comes closest to
+fn sync_index(sync: LfoSync) -> usize {
+    let LfoSync::Tempo(division) = sync else {
+        return 0;
+    };
+
+    SYNC_DIVISIONS
+        .iter()
+        .position(|s| *s == sync)
+        .unwrap_or_else(|| {
+            (1..SYNC_DIVISIONS.len())
+                .min_by(|a, b| {
+                    let distance = |i: &usize| match SYNC_DIVISIONS[*i] {
+                        LfoSync::Tempo(d) => (d.beats() / division.beats()).ln().abs(),
+                        LfoSync::Free => f64::INFINITY,
+                    };
+                    distance(a).total_cmp(&distance(b))
+                })
+                .unwrap_or(0)
+        })
+}
+
+/// the parameters shared between the host's main and audio threads
+pub struct SharedValues([AtomicU64; PARAMS.len()]);
+
+impl SharedValues {
+    pub fn new(values: &Values) -> Self {
+        Self(values.map(|value| AtomicU64::new(value.to_bits())))
+    }
+
+    pub fn load(&self) -> Values {
+        std::array::from_fn(|i| f64::from_bits(self.0[i].load(Ordering::Relaxed)))
+    }
+
+    pub fn get(&self, param: Param) -> f64 {
+        f64::from_bits(self.0[param as usize].load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, param: Param, value: f64) {
+        self.0[param as usize].store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn store(&self, values: &Values) {
+        for (param, value) in PARAMS.into_iter().zip(values) {
+            self.set(param, *value);
+        }
+    }
+}
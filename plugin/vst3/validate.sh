@@ -0,0 +1,20 @@
+#!/bin/sh
+# builds the VST3 SDK's validator and runs it on the VST3 in target/vst3, run from the workspace
+# root once the VST3 is built
+set -eu
+
+sdk=target/vst3sdk
+
+if [ ! -d "$sdk" ]; then
+    git clone --depth 1 --recurse-submodules --shallow-submodules \
+        https://github.com/steinbergmedia/vst3sdk "$sdk"
+fi
+
+cmake -S "$sdk" -B "$sdk/build" -DCMAKE_BUILD_TYPE=Release \
+    -DSMTG_ENABLE_VSTGUI_SUPPORT=OFF -DSMTG_ADD_VST3_PLUGINS_SAMPLES=OFF
+cmake --build "$sdk/build" --target validator --config Release
+
+validator=$(find "$sdk/build/bin" -name validator -type f | head -n 1)
+vst3=$(find target/vst3 -name mf-102.vst3 -prune | head -n 1)
+
+"$validator" "$vst3"
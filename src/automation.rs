@@ -0,0 +1,315 @@
+//! time-varying control of the knobs, like the hardware's pedal/CV inputs
+
+use crate::params::{FrequencyRange, RingModParams};
+use serde::Deserialize;
+use std::path::Path;
+use std::str::FromStr;
+use std::{fmt, fs, io};
+
+/// the voltage of a fully opened pedal, CV between 0V and this sweeps the whole knob
+const MAX_CV: f32 = 5.0;
+
+/// a knob that can be automated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Knob {
+    /// 0 to 100
+    Mix,
+    /// Hz within the selected range
+    Frequency,
+    /// 0.1Hz to 25Hz
+    Rate,
+    /// 0 to 10
+    Amount,
+    /// 0 to 10
+    Drive,
+}
+
+impl Knob {
+    /// the lowest and highest value of the knob
+    pub fn bounds(self, range: FrequencyRange) -> (f32, f32) {
+        match self {
+            Self::Mix => (0.0, 100.0),
+            Self::Frequency => range.bounds(),
+            Self::Rate => (0.1, 25.0),
+            Self::Amount | Self::Drive => (0.0, 10.0),
+        }
+    }
+
+    /// maps 0V to 5V onto the knob, following the dial's exponential taper for the frequency and rate
+    pub fn volts_to_value(self, volts: f32, range: FrequencyRange) -> f32 {
+        self.position_to_value(volts / MAX_CV, range)
+    }
+
+    /// maps a 0 to 1 position of the knob's travel onto its value, following the dial's
+    /// exponential taper for the frequency and rate
+    pub fn position_to_value(self, position: f32, range: FrequencyRange) -> f32 {
+        let position = position.clamp(0.0, 1.0);
+        let (min, max) = self.bounds(range);
+
+        match self {
+            Self::Frequency | Self::Rate => min * (max / min).powf(position),
+            Self::Mix | Self::Amount | Self::Drive => min + (max - min) * position,
+        }
+    }
+
+    pub(crate) fn set(self, params: &mut RingModParams, value: f32) {
+        let (min, max) = self.bounds(params.range);
+        let value = value.clamp(min, max);
+
+        match self {
+            Self::Mix => params.mix = value.round() as u8,
+            Self::Frequency => params.frequency = value,
+            Self::Rate => params.rate = value,
+            Self::Amount => params.amount = value,
+            Self::Drive => params.drive = value,
+        }
+    }
+}
+
+impl fmt::Display for Knob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Mix => "mix",
+            Self::Frequency => "frequency",
+            Self::Rate => "rate",
+            Self::Amount => "amount",
+            Self::Drive => "drive",
+        })
+    }
+}
+
+impl FromStr for Knob {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mix" => Ok(Self::Mix),
+            "frequency" => Ok(Self::Frequency),
+            "rate" => Ok(Self::Rate),
+            "amount" => Ok(Self::Amount),
+            "drive" => Ok(Self::Drive),
+            _ => Err(format!(
+                "unknown knob `{s}`, expected `mix`, `frequency`, `rate`, `amount` or `drive`"
+            )),
+        }
+    }
+}
+
+/// how an envelope moves from one breakpoint to the next
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+    /// a straight line
+    #[default]
+    Linear,
+    /// a constant ratio per second, falls back to linear when either value isn't positive
+    Exponential,
+}
+
+impl FromStr for Curve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "exponential" => Ok(Self::Exponential),
+            _ => Err(format!(
+                "unknown curve `{s}`, expected `linear` or `exponential`"
+            )),
+        }
+    }
+}
+
+/// a point of an envelope
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct Breakpoint {
+    /// seconds from the start
+    pub time: f64,
+    /// in the knob's units
+    pub value: f32,
+    /// how the envelope gets to this point from the previous one
+    #[serde(default)]
+    pub curve: Curve,
+}
+
+/// breakpoints sorted by time, held before the first and after the last one
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Envelope {
+    points: Vec<Breakpoint>,
+}
+
+impl Envelope {
+    /// an envelope through `points`, which don't need to be sorted
+    pub fn new(mut points: Vec<Breakpoint>) -> Result<Self, String> {
+        if points.is_empty() {
+            return Err("an envelope needs at least one breakpoint".to_string());
+        }
+
+        points.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Ok(Self { points })
+    }
+
+    /// reads `{"points": [{"time": 0.0, "value": 1.0, "curve": "linear"}, ...]}`
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let envelope: Self = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        Self::new(envelope.points)
+    }
+
+    /// reads `time,value` or `time,value,curve` lines, skipping a header and empty lines
+    pub fn from_csv(csv: &str) -> Result<Self, String> {
+        let mut points = vec![];
+
+        for (i, line) in csv.lines().enumerate() {
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+
+            if line.trim().is_empty() || (i == 0 && fields[0].parse::<f64>().is_err()) {
+                continue;
+            }
+
+            let err = |what: &str| format!("line {}: {what}", i + 1);
+
+            let (time, value, curve) = match fields[..] {
+                [time, value] => (time, value, None),
+                [time, value, curve] => (time, value, Some(curve)),
+                _ => return Err(err("expected `time,value` or `time,value,curve`")),
+            };
+
+            points.push(Breakpoint {
+                time: time.parse().map_err(|_| err("invalid time"))?,
+                value: value.parse().map_err(|_| err("invalid value"))?,
+                curve: curve
+                    .map_or(Ok(Curve::Linear), str::parse)
+                    .map_err(|e| err(&e))?,
+            });
+        }
+
+        Self::new(points)
+    }
+
+    /// the value at `time` seconds
+    pub fn value(&self, time: f64) -> f32 {
+        // the first point that's later than `time`
+        let next = self.points.partition_point(|p| p.time <= time);
+
+        let (Some(a), Some(b)) = (
+            next.checked_sub(1).map(|i| &self.points[i]),
+            self.points.get(next),
+        ) else {
+            // before the first or after the last point
+            return self.points[next.min(self.points.len() - 1)].value;
+        };
+
+        let t = ((time - a.time) / (b.time - a.time)) as f32;
+
+        match b.curve {
+            Curve::Exponential if a.value > 0.0 && b.value > 0.0 => {
+                a.value * (b.value / a.value).powf(t)
+            }
+            _ => a.value + (b.value - a.value) * t,
+        }
+    }
+}
+
+/// a control voltage recorded as a WAV file, full scale being 5V
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlVoltage {
+    /// volts of the first channel
+    volts: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl ControlVoltage {
+    /// `samples` are the interleaved, normalized samples of a control rate signal
+    pub fn new(samples: &[f32], channels: usize, sample_rate: u32) -> Self {
+        Self {
+            volts: samples
+                .iter()
+                .step_by(channels)
+                .map(|s| s * MAX_CV)
+                .collect(),
+            sample_rate,
+        }
+    }
+
+    /// the voltage at `time` seconds, interpolated between samples and held after the last one
+    pub fn volts(&self, time: f64) -> f32 {
+        let position = time * f64::from(self.sample_rate);
+        let i = position.floor() as usize;
+        let t = (position - position.floor()) as f32;
+
+        match (self.volts.get(i), self.volts.get(i + 1)) {
+            (Some(a), Some(b)) => a + (b - a) * t,
+            (Some(a), None) => *a,
+            _ => self.volts.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// where a knob's automation comes from
+#[derive(Clone, Debug, PartialEq)]
+pub enum ControlSource {
+    /// values in the knob's units
+    Envelope(Envelope),
+    /// a pedal or CV, mapped onto the knob's range
+    ControlVoltage(ControlVoltage),
+}
+
+impl ControlSource {
+    /// loads a CV from a `.wav` file, or an envelope from a `.json` or `.csv` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        let read = || fs::read_to_string(path).map_err(|e: io::Error| e.to_string());
+
+        match extension {
+            "wav" => {
+                let (spec, samples) = crate::wav::read(path).map_err(|e| e.to_string())?;
+                Ok(Self::ControlVoltage(ControlVoltage::new(
+                    &samples,
+                    usize::from(spec.channels),
+                    spec.sample_rate,
+                )))
+            }
+            "json" => Envelope::from_json(&read()?).map(Self::Envelope),
+            "csv" => Envelope::from_csv(&read()?).map(Self::Envelope),
+            _ => Err("expected a `.wav`, `.json` or `.csv` file".to_string()),
+        }
+    }
+
+    fn value(&self, knob: Knob, time: f64, range: FrequencyRange) -> f32 {
+        match self {
+            Self::Envelope(envelope) => envelope.value(time),
+            Self::ControlVoltage(cv) => knob.volts_to_value(cv.volts(time), range),
+        }
+    }
+}
+
+/// the knobs that are automated and where their values come from
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Automation {
+    lanes: Vec<(Knob, ControlSource)>,
+}
+
+impl Automation {
+    /// automates `knob` with `source`, replacing any earlier automation of it
+    pub fn add(&mut self, knob: Knob, source: ControlSource) {
+        self.lanes.retain(|(k, _)| *k != knob);
+        self.lanes.push((knob, source));
+    }
+
+    /// whether no knob is automated
+    pub fn is_empty(&self) -> bool {
+        self.lanes.is_empty()
+    }
+
+    /// turns the automated knobs of `params` to where they are at `time` seconds
+    pub fn apply(&self, params: &mut RingModParams, time: f64) {
+        for (knob, source) in &self.lanes {
+            knob.set(params, source.value(*knob, time, params.range));
+        }
+    }
+}
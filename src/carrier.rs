@@ -0,0 +1,147 @@
+//! an external signal replacing the internal carrier oscillator, like the Carrier In jack
+
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+/// taps on either side of the resampling filter
+const RESAMPLER_TAPS: isize = 16;
+
+/// what happens once the external carrier is shorter than the input
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CarrierEnd {
+    /// starts the carrier over from its beginning
+    Loop,
+    /// the carrier goes silent, silencing the ring modulated part of the output
+    Silence,
+}
+
+impl FromStr for CarrierEnd {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "loop" => Ok(Self::Loop),
+            "silence" => Ok(Self::Silence),
+            _ => Err(format!(
+                "unknown carrier end `{s}`, expected `loop` or `silence`"
+            )),
+        }
+    }
+}
+
+/// an external carrier conformed to the input's sample rate and channel count
+pub struct ExternalCarrier {
+    /// interleaved, normalized frames with the input's layout
+    samples: Vec<f32>,
+    channels: usize,
+    position: usize,
+    end: CarrierEnd,
+}
+
+impl ExternalCarrier {
+    /// conforms the interleaved, normalized `signal` with `signal_channels` channels at
+    /// `signal_rate` to `channels` channels at `sample_rate`
+    ///
+    /// a mono carrier feeds every channel, a multichannel carrier is averaged down for a mono
+    /// input and otherwise its channels are assigned round-robin
+    pub fn new(
+        signal: &[f32],
+        signal_rate: u32,
+        signal_channels: usize,
+        sample_rate: u32,
+        channels: usize,
+        end: CarrierEnd,
+    ) -> Self {
+        let frames = signal.len() / signal_channels;
+
+        let remapped = (0..frames)
+            .flat_map(|frame| {
+                let frame = &signal[frame * signal_channels..(frame + 1) * signal_channels];
+
+                (0..channels).map(move |channel| {
+                    if channels == 1 {
+                        frame.iter().sum::<f32>() / signal_channels as f32
+                    } else {
+                        frame[channel % signal_channels]
+                    }
+                })
+            })
+            .collect::<Vec<f32>>();
+
+        let samples = if signal_rate == sample_rate {
+            remapped
+        } else {
+            resample(&remapped, channels, signal_rate, sample_rate)
+        };
+
+        Self {
+            samples,
+            channels,
+            position: 0,
+            end,
+        }
+    }
+
+    /// fills `out` with the next interleaved frames of the carrier
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for frame in out.chunks_exact_mut(self.channels) {
+            if self.position >= self.samples.len() {
+                match self.end {
+                    CarrierEnd::Loop if !self.samples.is_empty() => self.position = 0,
+                    _ => {
+                        frame.fill(0.0);
+                        continue;
+                    }
+                }
+            }
+
+            frame.copy_from_slice(&self.samples[self.position..self.position + self.channels]);
+            self.position += self.channels;
+        }
+    }
+}
+
+/// band-limited interpolation of interleaved `samples` from `from` to `to` Hz
+fn resample(samples: &[f32], channels: usize, from: u32, to: u32) -> Vec<f32> {
+    let frames = samples.len() / channels;
+    let step = f64::from(from) / f64::from(to);
+    let out_frames = (frames as f64 / step).floor() as usize;
+    // when downsampling the filter has to cut below the new nyquist
+    let cutoff = (1.0 / step).min(1.0) as f32;
+
+    let mut out = Vec::with_capacity(out_frames * channels);
+
+    for frame in 0..out_frames {
+        let t = frame as f64 * step;
+        let center = t.floor() as isize;
+        let fraction = (t - t.floor()) as f32;
+
+        for channel in 0..channels {
+            let mut acc = 0.0;
+
+            for tap in -RESAMPLER_TAPS + 1..=RESAMPLER_TAPS {
+                let Some(sample) = usize::try_from(center + tap)
+                    .ok()
+                    .and_then(|i| samples.get(i * channels + channel))
+                else {
+                    continue;
+                };
+
+                let x = tap as f32 - fraction;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * cutoff * x).sin() / (PI * cutoff * x)
+                };
+                // hann window over the filter's span
+                let window = 0.5 + 0.5 * (PI * x / RESAMPLER_TAPS as f32).cos();
+
+                acc += sample * cutoff * sinc * window;
+            }
+
+            out.push(acc);
+        }
+    }
+
+    out
+}
@@ -0,0 +1,216 @@
+//! keeps the output within full scale
+
+use crate::oversample::Oversampler;
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+/// the true-peak limiter's ceiling, -1dBTP
+const LIMITER_CEILING: f32 = 0.891;
+/// how far ahead the limiter starts reducing the gain of a peak, in seconds
+const LIMITER_LOOKAHEAD: f32 = 0.0015;
+/// time constant of the limiter recovering its gain after a peak, in seconds
+const LIMITER_RELEASE: f32 = 0.05;
+/// oversampling factor used to find peaks between samples
+const TRUE_PEAK_OVERSAMPLING: usize = 4;
+
+/// where the soft clipper's knee starts
+const SOFT_CLIP_KNEE: f32 = 0.9;
+
+/// how samples over full scale are brought back within it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClipMode {
+    /// clamps samples to full scale
+    Hard,
+    /// rounds samples above the knee off towards full scale
+    Soft,
+    /// lookahead limiter keeping inter-sample peaks below the ceiling
+    Limiter,
+}
+
+impl FromStr for ClipMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hard" => Ok(Self::Hard),
+            "soft" => Ok(Self::Soft),
+            "limiter" => Ok(Self::Limiter),
+            _ => Err(format!(
+                "unknown clip mode `{s}`, expected `hard`, `soft` or `limiter`"
+            )),
+        }
+    }
+}
+
+/// brings interleaved normalized samples within full scale, block by block
+pub struct Clipper {
+    mode: ClipMode,
+    channels: usize,
+    /// number of samples that were over full scale
+    clipped: usize,
+    limiter: Option<Limiter>,
+}
+
+impl Clipper {
+    /// a clipper for `channels` interleaved channels at `sample_rate`
+    pub fn new(mode: ClipMode, channels: usize, sample_rate: u32) -> Self {
+        Self {
+            mode,
+            channels,
+            clipped: 0,
+            limiter: matches!(mode, ClipMode::Limiter).then(|| Limiter::new(channels, sample_rate)),
+        }
+    }
+
+    /// appends the processed `samples` to `out`, the limiter holds back its lookahead
+    pub fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        self.clipped += samples.iter().filter(|s| s.abs() > 1.0).count();
+
+        match self.mode {
+            ClipMode::Hard => out.extend(samples.iter().map(|s| s.clamp(-1.0, 1.0))),
+            ClipMode::Soft => out.extend(samples.iter().copied().map(soft_clip)),
+            ClipMode::Limiter => {
+                let limiter = self.limiter.as_mut().expect("limiter mode has a limiter");
+
+                for frame in samples.chunks_exact(self.channels) {
+                    limiter.push(frame, out);
+                }
+            }
+        }
+    }
+
+    /// appends whatever the limiter still holds back to `out`,
+    /// returns the number of samples that were over full scale
+    pub fn finish(&mut self, out: &mut Vec<f32>) -> usize {
+        if let Some(limiter) = &mut self.limiter {
+            limiter.flush(out);
+        }
+
+        self.clipped
+    }
+}
+
+fn soft_clip(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+
+    if magnitude <= SOFT_CLIP_KNEE {
+        sample
+    } else {
+        let headroom = 1.0 - SOFT_CLIP_KNEE;
+        let rounded = SOFT_CLIP_KNEE + headroom * ((magnitude - SOFT_CLIP_KNEE) / headroom).tanh();
+        rounded.copysign(sample)
+    }
+}
+
+/// lookahead limiter linking all channels, delays its output by `lookahead` plus the true
+/// peak detection's delay
+struct Limiter {
+    channels: usize,
+    lookahead: usize,
+    release: f32,
+    /// one per channel, to find peaks between samples
+    oversamplers: Vec<Oversampler>,
+    oversampled: [f32; TRUE_PEAK_OVERSAMPLING],
+    /// frames that haven't been output yet
+    delay_line: VecDeque<f32>,
+    /// the true peak of every frame in `delay_line`
+    peaks: VecDeque<f32>,
+    /// the released gains of the last `lookahead + 1` frames and their sum
+    gains: VecDeque<f32>,
+    gain_sum: f64,
+    gain: f32,
+    /// frames pushed and output, so flushing stops at the end of the input
+    frames_in: usize,
+    frames_out: usize,
+}
+
+impl Limiter {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        let oversamplers = (0..channels)
+            .map(|_| Oversampler::new(TRUE_PEAK_OVERSAMPLING))
+            .collect();
+
+        Self {
+            channels,
+            lookahead: (LIMITER_LOOKAHEAD * sample_rate as f32).ceil() as usize,
+            release: (-1.0 / (LIMITER_RELEASE * sample_rate as f32)).exp(),
+            oversamplers,
+            oversampled: [0.0; TRUE_PEAK_OVERSAMPLING],
+            delay_line: VecDeque::new(),
+            peaks: VecDeque::new(),
+            gains: VecDeque::new(),
+            gain_sum: 0.0,
+            gain: 1.0,
+            frames_in: 0,
+            frames_out: 0,
+        }
+    }
+
+    /// the true peak of the most recent frame is only known after this many more frames
+    fn peak_delay(&self) -> usize {
+        self.oversamplers[0].delay()
+    }
+
+    fn push(&mut self, frame: &[f32], out: &mut Vec<f32>) {
+        self.frames_in += 1;
+        self.process(frame, out);
+    }
+
+    fn flush(&mut self, out: &mut Vec<f32>) {
+        let silence = vec![0.0; self.channels];
+
+        while self.frames_out < self.frames_in {
+            self.process(&silence, out);
+        }
+    }
+
+    fn process(&mut self, frame: &[f32], out: &mut Vec<f32>) {
+        self.delay_line.extend(frame);
+        self.peaks
+            .push_back(frame.iter().fold(0.0f32, |a, b| a.max(b.abs())));
+
+        // the upsampled peaks belong to the frame `peak_delay` frames back
+        let delay = self.peak_delay();
+
+        for (oversampler, &sample) in self.oversamplers.iter_mut().zip(frame) {
+            oversampler.upsample(sample, &mut self.oversampled);
+
+            if let Some(peak) = self
+                .peaks
+                .len()
+                .checked_sub(delay + 1)
+                .and_then(|i| self.peaks.get_mut(i))
+            {
+                *peak = self.oversampled.iter().fold(*peak, |a, b| a.max(b.abs()));
+            }
+        }
+
+        if self.peaks.len() <= self.lookahead + delay {
+            return;
+        }
+
+        // start reducing the gain `lookahead` frames ahead of a peak and recover slowly afterwards
+        let peak = self
+            .peaks
+            .range(..=self.lookahead)
+            .fold(0.0f32, |a, &b| a.max(b));
+        let required = (LIMITER_CEILING / peak).min(1.0);
+
+        self.gain = required.min(1.0 - (1.0 - self.gain) * self.release);
+
+        // smoothing over the lookahead window can only pull the gain further down, as every
+        // frame in the window already accounts for the peak `lookahead` frames ahead of it
+        self.gains.push_back(self.gain);
+        self.gain_sum += f64::from(self.gain);
+
+        if self.gains.len() > self.lookahead + 1 {
+            self.gain_sum -= f64::from(self.gains.pop_front().unwrap_or_default());
+        }
+
+        let gain = (self.gain_sum / self.gains.len() as f64) as f32;
+
+        self.peaks.pop_front();
+        out.extend(self.delay_line.drain(..self.channels).map(|s| s * gain));
+        self.frames_out += 1;
+    }
+}
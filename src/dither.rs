@@ -0,0 +1,91 @@
+//! dithered quantization of normalized samples to integer output
+
+use crate::rng::Rng;
+use std::str::FromStr;
+
+/// error feedback filter of the noise shaper, pushes the requantization noise above the
+/// most sensitive band of hearing, see Lipshitz et al. "Minimally audible noise shaping" (1991)
+const NOISE_SHAPING: [f32; 3] = [1.623, -0.982, 0.109];
+
+/// noise added when quantizing to hide the quantization error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// plain rounding
+    None,
+    /// triangular probability density function dither, 2 LSB peak to peak
+    Tpdf,
+    /// TPDF dither with the requantization noise shaped out of the most audible band
+    Shaped,
+}
+
+impl FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "tpdf" => Ok(Self::Tpdf),
+            "shaped" => Ok(Self::Shaped),
+            _ => Err(format!(
+                "unknown dither `{s}`, expected `none`, `tpdf` or `shaped`"
+            )),
+        }
+    }
+}
+
+/// quantizes normalized samples to `bits` bit integers
+pub struct Quantizer {
+    full_scale: f32,
+    dither: Dither,
+    rng: Rng,
+    /// the most recent requantization errors of every channel, newest first
+    errors: Vec<[f32; NOISE_SHAPING.len()]>,
+}
+
+impl Quantizer {
+    /// a quantizer for `channels` interleaved channels, dither is skipped above 24 bits
+    pub fn new(bits: u16, channels: usize, dither: Dither) -> Self {
+        // 32-bit integers are already finer than an f32's mantissa
+        let dither = if bits > 24 { Dither::None } else { dither };
+
+        Self {
+            full_scale: (1i64 << (bits - 1)) as f32,
+            dither,
+            rng: Rng::new(0),
+            errors: vec![[0.0; NOISE_SHAPING.len()]; channels],
+        }
+    }
+
+    /// quantizes a normalized sample of `channel`, clamping it to the integer range
+    pub fn quantize(&mut self, channel: usize, sample: f32) -> i32 {
+        let sample = sample * self.full_scale;
+
+        let quantized = match self.dither {
+            Dither::None => sample.round(),
+            Dither::Tpdf => (sample + self.tpdf()).round(),
+            Dither::Shaped => {
+                let tpdf = self.tpdf();
+                let errors = &mut self.errors[channel];
+                let shaped = sample
+                    - NOISE_SHAPING
+                        .iter()
+                        .zip(errors.iter())
+                        .map(|(h, e)| h * e)
+                        .sum::<f32>();
+                let quantized = (shaped + tpdf).round();
+
+                errors.rotate_right(1);
+                errors[0] = quantized - shaped;
+
+                quantized
+            }
+        };
+
+        quantized.clamp(-self.full_scale, self.full_scale - 1.0) as i32
+    }
+
+    /// triangular noise between -1 and 1 LSB
+    fn tpdf(&mut self) -> f32 {
+        self.rng.next_f32() - self.rng.next_f32()
+    }
+}
@@ -0,0 +1,73 @@
+//! the MF-102's input drive stage
+
+use crate::oversample::Oversampler;
+
+/// gain of the drive stage with the knob fully clockwise
+const MAX_DRIVE_DB: f32 = 30.0;
+
+/// oversampling factor around the soft clipper
+const OVERSAMPLING: usize = 4;
+
+fn gain(drive: f32) -> f32 {
+    10f32.powf(drive / 10.0 * MAX_DRIVE_DB / 20.0)
+}
+
+/// models the input gain and the soft clipping of the analog input ahead of the multiplier
+///
+/// a drive of 0 bypasses the stage entirely, unless it's engaged from the start so the knob can be
+/// turned while processing
+pub struct Drive {
+    gain: f32,
+    /// whether the stage is in the signal path, fixed at construction so the latency never changes
+    engaged: bool,
+    oversampler: Oversampler,
+    buffer: [f32; OVERSAMPLING],
+}
+
+impl Drive {
+    /// `drive` is the 0 to 10 knob position, `engaged` puts the stage in the signal path even at 0
+    pub fn new(drive: f32, engaged: bool) -> Self {
+        let gain = gain(drive);
+
+        Self {
+            gain,
+            engaged: engaged || gain != 1.0,
+            oversampler: Oversampler::new(OVERSAMPLING),
+            buffer: [0.0; OVERSAMPLING],
+        }
+    }
+
+    /// turns the knob without clearing the filters, a bypassed stage stays bypassed
+    pub fn set_drive(&mut self, drive: f32) {
+        self.gain = gain(drive);
+    }
+
+    /// clears the oversampling filters
+    pub fn reset(&mut self) {
+        self.oversampler = Oversampler::new(OVERSAMPLING);
+    }
+
+    /// delay added by the stage in samples
+    pub fn latency(&self) -> f32 {
+        if self.engaged {
+            self.oversampler.latency()
+        } else {
+            0.0
+        }
+    }
+
+    /// processes a single sample normalized to -1.0..=1.0
+    pub fn process(&mut self, sample: f32) -> f32 {
+        if !self.engaged {
+            return sample;
+        }
+
+        self.oversampler.upsample(sample, &mut self.buffer);
+
+        for s in &mut self.buffer {
+            *s = (*s * self.gain).tanh();
+        }
+
+        self.oversampler.downsample(&self.buffer)
+    }
+}
@@ -0,0 +1,537 @@
+//! the ring modulator itself
+
+use crate::automation::{Automation, Knob};
+use crate::drive::Drive;
+use crate::multiplier::{self, MultiplierModel, Oversampled};
+use crate::oscillator;
+use crate::params::{CarrierWaveform, LfoModulation, LfoSync, RingModParams, Waveform};
+use crate::rng::Rng;
+use crate::smoothing::{Smoother, Smoothing, SmoothingMode};
+use crate::vintage::{self, Vintage};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+/// seeds the random LFO waveforms, so renders stay reproducible
+const LFO_SEED: u64 = 102;
+
+/// block based ring modulator keeping its oscillators' phases between blocks
+///
+/// the carrier and LFO advance once per frame, so every channel of a frame is modulated by the same carrier
+pub struct RingModulator {
+    params: RingModParams,
+    sample_rate: u32,
+    channels: usize,
+    lfo_phase: f32,
+    carrier_phase: f32,
+    /// one input stage per channel
+    drive: Vec<Drive>,
+    /// the drive the input stages are currently set to
+    drive_value: f32,
+    /// glides of the knobs towards the settings
+    smoothers: Smoothers,
+    /// turns the knobs during processing
+    automation: Automation,
+    /// frames processed since the start or the last reset, the automation's clock
+    position: u64,
+    /// beats per minute a tempo synced LFO follows
+    tempo: f64,
+    /// beats since the start of the song, the phase of a tempo synced LFO
+    song_position: f64,
+    /// the tolerances and drift of the unit the settings pick
+    vintage: Vintage,
+    /// draws the random LFO waveforms' values
+    lfo_rng: Rng,
+    /// the random LFO waveforms' values at the start and the end of the current cycle
+    lfo_random: [f32; 2],
+    /// rounds the square LFO's edges
+    lfo_slew: Smoother,
+    /// whether the LFO was square on the last frame, `lfo_slew` only follows it while it is
+    lfo_square: bool,
+    /// the factor the multiplier is oversampled by, fixed so the latency is too
+    oversampling: usize,
+    /// every channel's oversampled multiplier, none without oversampling
+    oversampled: Vec<Oversampled>,
+    /// the LFO stem, delayed to line up with the oversampled multiplier's output
+    lfo_delay: VecDeque<f32>,
+}
+
+impl RingModulator {
+    /// a ring modulator for `channels` interleaved channels at `sample_rate`, panics with 0 channels
+    ///
+    /// the input stage is only in the signal path if `params` turn the drive up, turning it up
+    /// later needs [`with_drive_stage`](Self::with_drive_stage)
+    pub fn new(params: RingModParams, sample_rate: u32, channels: usize) -> Self {
+        Self::build(params, sample_rate, channels, false)
+    }
+
+    /// a ring modulator like [`new`](Self::new) with the input stage in the signal path even at a
+    /// drive of 0, so the drive can be turned while processing without the latency changing
+    pub fn with_drive_stage(params: RingModParams, sample_rate: u32, channels: usize) -> Self {
+        Self::build(params, sample_rate, channels, true)
+    }
+
+    fn build(params: RingModParams, sample_rate: u32, channels: usize, drive_stage: bool) -> Self {
+        assert!(channels > 0, "a ring modulator needs at least 1 channel");
+
+        let drive = (0..channels)
+            .map(|_| Drive::new(params.drive, drive_stage))
+            .collect();
+        let oversampling = oversampling(&params);
+        let oversampled = oversampled(oversampling, channels);
+        let (lfo_rng, lfo_random) = random_lfo();
+
+        Self {
+            sample_rate,
+            channels,
+            lfo_phase: 0.0,
+            carrier_phase: 0.0,
+            drive,
+            drive_value: params.drive,
+            smoothers: Smoothers::new(&params, sample_rate),
+            vintage: Vintage::new(params.unit, sample_rate),
+            oversampling,
+            lfo_delay: lfo_delay(&oversampled),
+            oversampled,
+            lfo_rng,
+            lfo_random,
+            lfo_slew: Smoother::new(0.0, false, slew(params.lfo_slew), sample_rate),
+            lfo_square: false,
+            params,
+            automation: Automation::default(),
+            position: 0,
+            tempo: 120.0,
+            song_position: 0.0,
+        }
+    }
+
+    /// the current settings
+    pub fn params(&self) -> &RingModParams {
+        &self.params
+    }
+
+    /// changes the settings from the next block on, keeping the oscillators' phases,
+    /// the knobs glide to their new positions as set with [`set_smoothing`](Self::set_smoothing)
+    pub fn set_params(&mut self, params: RingModParams) {
+        self.params = params;
+    }
+
+    /// how `knob` glides when it's turned, the frequency and rate glide in octaves
+    pub fn set_smoothing(&mut self, knob: Knob, smoothing: Smoothing) {
+        self.smoothers
+            .get_mut(knob)
+            .configure(smoothing, self.sample_rate);
+    }
+
+    /// turns the knobs of the settings sample-accurately while processing,
+    /// with time counting from the start or the last reset
+    pub fn set_automation(&mut self, automation: Automation) {
+        self.automation = automation;
+    }
+
+    /// the tempo in beats per minute a synced LFO follows from the next block on, 120 until it's set
+    pub fn set_tempo(&mut self, bpm: f64) {
+        self.tempo = bpm;
+    }
+
+    /// the tempo in beats per minute
+    pub fn tempo(&self) -> f64 {
+        self.tempo
+    }
+
+    /// moves the song to `beats` from its start, a synced LFO jumping to the phase it has there
+    ///
+    /// bars start on multiples of 4 beats, so a synced LFO starts every bar in phase when a
+    /// whole number of its cycles fit into a bar, and every few bars otherwise
+    pub fn set_song_position(&mut self, beats: f64) {
+        self.song_position = beats;
+    }
+
+    /// beats since the start of the song, advancing at the tempo
+    pub fn song_position(&self) -> f64 {
+        self.song_position
+    }
+
+    /// puts the oscillators, the song and the automation back to the start and clears the input stages
+    pub fn reset(&mut self) {
+        self.position = 0;
+        self.song_position = 0.0;
+        self.lfo_phase = 0.0;
+        self.carrier_phase = 0.0;
+        self.smoothers.set_targets(&self.params);
+        self.smoothers.snap();
+        self.drive.iter_mut().for_each(Drive::reset);
+        self.vintage = Vintage::new(self.params.unit, self.sample_rate);
+        (self.lfo_rng, self.lfo_random) = random_lfo();
+        self.lfo_slew.set_target(0.0);
+        self.lfo_slew.snap();
+        self.lfo_square = false;
+        self.oversampled = oversampled(self.oversampling, self.channels);
+        self.lfo_delay = lfo_delay(&self.oversampled);
+    }
+
+    /// the oscillators keep their phase, only their increments change
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.smoothers.set_sample_rate(sample_rate);
+        self.vintage.set_sample_rate(sample_rate);
+    }
+
+    /// whether the oversampling's been changed, which would change the latency, so it keeps the
+    /// factor it was created with
+    pub fn oversampling_changed(&self) -> bool {
+        oversampling(&self.params) != self.oversampling
+    }
+
+    /// delay between the input and the output in frames
+    pub fn latency(&self) -> f32 {
+        self.drive.first().map_or(0.0, Drive::latency)
+            + self.oversampled.first().map_or(0.0, Oversampled::latency)
+    }
+
+    /// ring modulates whole frames of interleaved, normalized `input` into `output`
+    pub fn process_block(&mut self, input: &[f32], output: &mut [f32]) {
+        self.process(input, None, output, None);
+    }
+
+    /// ring modulates `input` with an external `carrier` instead of the internal oscillator,
+    /// `carrier` has the same layout as `input`, see [`ExternalCarrier`](crate::carrier::ExternalCarrier)
+    pub fn process_block_with_carrier(
+        &mut self,
+        input: &[f32],
+        carrier: &[f32],
+        output: &mut [f32],
+    ) {
+        debug_assert_eq!(input.len(), carrier.len());
+
+        self.process(input, Some(carrier), output, None);
+    }
+
+    /// ring modulates `input` like [`process_block`](Self::process_block), or
+    /// [`process_block_with_carrier`](Self::process_block_with_carrier) with a `carrier`,
+    /// writing the signals that make up `output` into `stems`
+    pub fn process_block_with_stems(
+        &mut self,
+        input: &[f32],
+        carrier: Option<&[f32]>,
+        output: &mut [f32],
+        stems: &mut Stems,
+    ) {
+        debug_assert_eq!(stems.lfo.len(), input.len() / self.channels);
+
+        self.process(input, carrier, output, Some(stems));
+    }
+
+    fn process(
+        &mut self,
+        input: &[f32],
+        external: Option<&[f32]>,
+        output: &mut [f32],
+        mut stems: Option<&mut Stems>,
+    ) {
+        debug_assert_eq!(input.len(), output.len());
+
+        let mut params = self.params.clone();
+        let sample_rate = self.sample_rate;
+        let channels = self.channels;
+        let multiplier = MultiplierModel::new(&params);
+
+        self.lfo_slew.configure(slew(params.lfo_slew), sample_rate);
+
+        if self.vintage.unit() != params.unit {
+            self.vintage = Vintage::new(params.unit, sample_rate);
+        }
+
+        for (frame, (input, output)) in input
+            .chunks_exact(channels)
+            .zip(output.chunks_exact_mut(channels))
+            .enumerate()
+        {
+            if !self.automation.is_empty() {
+                let time = self.position as f64 / f64::from(sample_rate);
+                self.automation.apply(&mut params, time);
+            }
+
+            self.position += 1;
+
+            self.smoothers.set_targets(&params);
+
+            let drive = self.smoothers.drive.next();
+
+            if drive != self.drive_value {
+                self.drive_value = drive;
+                self.drive.iter_mut().for_each(|d| d.set_drive(drive));
+            }
+
+            // normalized mix and amount parameter
+            let mix = self.smoothers.mix.next() / 100.0;
+            let amount = self.smoothers.amount.next() / 10.0;
+            let mut frequency = self.smoothers.frequency.next();
+            let mut rate = self.smoothers.rate.next();
+
+            if params.vintage {
+                let vintage = &mut self.vintage;
+                frequency *=
+                    vintage.carrier_tolerance * vintage.carrier_drift.next_ratio(params.drift);
+                rate *= vintage.lfo_tolerance * vintage.lfo_drift.next_ratio(params.drift);
+            }
+
+            let lfo_increment = 2.0 * PI * rate / sample_rate as f32;
+            let channel_phase_offset = params.channel_phase_offset.to_radians();
+
+            let previous_lfo_phase = self.lfo_phase;
+
+            self.lfo_phase = match params.sync {
+                LfoSync::Free => (self.lfo_phase + lfo_increment).rem_euclid(2.0 * PI),
+                LfoSync::Tempo(division) => {
+                    let cycles = self.song_position / division.beats();
+                    (2.0 * std::f64::consts::PI * cycles.rem_euclid(1.0)) as f32
+                }
+            };
+            self.song_position += self.tempo / 60.0 / f64::from(sample_rate);
+
+            // a new random value for every cycle of the LFO
+            if self.lfo_phase < previous_lfo_phase {
+                self.lfo_random = [self.lfo_random[1], bipolar(&mut self.lfo_rng)];
+            }
+
+            // leaning with the unit's asymmetry
+            let phase = if params.vintage {
+                vintage::skew(self.lfo_phase, self.vintage.lfo_asymmetry)
+            } else {
+                self.lfo_phase
+            };
+            // the fraction of the cycle the LFO is at
+            let cycle = phase / (2.0 * PI);
+
+            let lfo = match params.lfo_waveform {
+                Waveform::Sinusoidal => phase.sin(),
+                Waveform::Square => {
+                    let mut duty = params.pulse_width.clamp(5.0, 95.0) / 100.0;
+
+                    if params.vintage {
+                        duty += self.vintage.duty_offset;
+                    }
+
+                    self.lfo_slew
+                        .set_target(if self.lfo_phase < 2.0 * PI * duty {
+                            1.0
+                        } else {
+                            0.0
+                        });
+
+                    // switching to the square starts on its level rather than a stale one
+                    if !self.lfo_square {
+                        self.lfo_slew.snap();
+                    }
+
+                    self.lfo_slew.next()
+                }
+                Waveform::Triangle => 4.0 * ((cycle - 0.25).rem_euclid(1.0) - 0.5).abs() - 1.0,
+                Waveform::RampUp => 2.0 * cycle - 1.0,
+                Waveform::RampDown => 1.0 - 2.0 * cycle,
+                Waveform::SampleAndHold => self.lfo_random[1],
+                Waveform::SmoothRandom => {
+                    let [from, to] = self.lfo_random;
+                    let t = cycle * cycle * (3.0 - 2.0 * cycle);
+                    from + (to - from) * t
+                }
+            };
+
+            self.lfo_square = params.lfo_waveform == Waveform::Square;
+
+            // the carrier's advance this frame in radians
+            let mut carrier_increment = 0.0;
+
+            // an external carrier replaces the oscillator, the LFO keeps running like the hardware's
+            if external.is_none() {
+                let carrier_frequency = match params.lfo_modulation {
+                    LfoModulation::Octaves => {
+                        // every waveform from 0 to 1, sweeping up from the frequency
+                        let sweep = match params.lfo_waveform {
+                            Waveform::Square => lfo,
+                            _ => (lfo + 1.0) / 2.0,
+                        };
+                        frequency * (3.0 * amount * sweep).exp2()
+                    }
+                    LfoModulation::Linear => frequency + lfo * (frequency * 3.0 * amount),
+                };
+
+                // the carrier signal that's applied to the sampled one
+                carrier_increment = 2.0 * PI * carrier_frequency / sample_rate as f32;
+
+                self.carrier_phase = (self.carrier_phase + carrier_increment).rem_euclid(2.0 * PI);
+            }
+
+            if let Some(stems) = &mut stems {
+                self.lfo_delay.push_back(lfo);
+                stems.lfo[frame] = self.lfo_delay.pop_front().unwrap_or(lfo);
+            }
+
+            for (channel, ((sample, out), drive)) in input
+                .iter()
+                .zip(output.iter_mut())
+                .zip(self.drive.iter_mut())
+                .enumerate()
+            {
+                let i = frame * channels + channel;
+
+                let carrier = match external {
+                    Some(external) => external[i],
+                    None => {
+                        let mut phase = self.carrier_phase + channel as f32 * channel_phase_offset;
+
+                        if params.vintage {
+                            phase = vintage::skew(phase, self.vintage.carrier_asymmetry);
+                        }
+
+                        match params.carrier_waveform {
+                            CarrierWaveform::Sine => phase.sin(),
+                            waveform => oscillator::band_limited(
+                                waveform,
+                                phase / (2.0 * PI),
+                                carrier_increment / (2.0 * PI),
+                                params.carrier_pulse_width,
+                            ),
+                        }
+                    }
+                };
+                let sample = drive.process(*sample);
+                let (sample, carrier, wet) = match self.oversampled.get_mut(channel) {
+                    Some(oversampled) => oversampled.multiply(&multiplier, sample, carrier),
+                    None => (sample, carrier, multiplier.multiply(sample, carrier)),
+                };
+
+                // accounted for the mix parameter
+                // see https://en.wikipedia.org/wiki/Ring_modulation#Simplified_operation
+                *out = (sample * (1.0 - mix)) + (wet * mix);
+
+                if let Some(stems) = &mut stems {
+                    stems.carrier[i] = carrier;
+                    stems.dry[i] = sample;
+                    stems.wet[i] = wet;
+                }
+            }
+        }
+    }
+}
+
+/// how the square LFO glides between its levels, `ms` being how long it takes to get 99% of the way
+fn slew(ms: f32) -> Smoothing {
+    Smoothing {
+        mode: SmoothingMode::OnePole,
+        // a one-pole gets 99% of the way in ln(100) time constants
+        time: ms.clamp(0.0, 100.0) / 1000.0 / 100f32.ln(),
+    }
+}
+
+/// the factor `params` oversample the multiplier by, rounded up to the next one there is
+fn oversampling(params: &RingModParams) -> usize {
+    (params.oversampling as usize)
+        .clamp(1, multiplier::MAX_OVERSAMPLING)
+        .next_power_of_two()
+}
+
+/// an oversampled multiplier for each of `channels`, or none with a `factor` of 1
+fn oversampled(factor: usize, channels: usize) -> Vec<Oversampled> {
+    if factor == 1 {
+        Vec::new()
+    } else {
+        (0..channels).map(|_| Oversampled::new(factor)).collect()
+    }
+}
+
+/// the LFO stem's delay, as long as the `oversampled` multipliers'
+fn lfo_delay(oversampled: &[Oversampled]) -> VecDeque<f32> {
+    let latency = oversampled.first().map_or(0.0, Oversampled::latency);
+
+    VecDeque::from(vec![0.0; latency as usize])
+}
+
+/// the random LFO waveforms' generator and the values of their first cycle
+fn random_lfo() -> (Rng, [f32; 2]) {
+    let mut rng = Rng::new(LFO_SEED);
+    let random = [bipolar(&mut rng), bipolar(&mut rng)];
+
+    (rng, random)
+}
+
+/// uniformly distributed in -1.0..1.0
+fn bipolar(rng: &mut Rng) -> f32 {
+    rng.next_f32() * 2.0 - 1.0
+}
+
+/// a smoother for every knob that can be turned while processing
+struct Smoothers {
+    mix: Smoother,
+    frequency: Smoother,
+    rate: Smoother,
+    amount: Smoother,
+    drive: Smoother,
+}
+
+impl Smoothers {
+    fn new(params: &RingModParams, sample_rate: u32) -> Self {
+        let smoother = |value, log| Smoother::new(value, log, Smoothing::default(), sample_rate);
+
+        Self {
+            mix: smoother(f32::from(params.mix), false),
+            frequency: smoother(params.range.clamp(params.frequency), true),
+            rate: smoother(params.rate, true),
+            amount: smoother(params.amount, false),
+            drive: smoother(params.drive, false),
+        }
+    }
+
+    fn get_mut(&mut self, knob: Knob) -> &mut Smoother {
+        match knob {
+            Knob::Mix => &mut self.mix,
+            Knob::Frequency => &mut self.frequency,
+            Knob::Rate => &mut self.rate,
+            Knob::Amount => &mut self.amount,
+            Knob::Drive => &mut self.drive,
+        }
+    }
+
+    fn all_mut(&mut self) -> [&mut Smoother; 5] {
+        [
+            &mut self.mix,
+            &mut self.frequency,
+            &mut self.rate,
+            &mut self.amount,
+            &mut self.drive,
+        ]
+    }
+
+    fn set_targets(&mut self, params: &RingModParams) {
+        self.mix.set_target(f32::from(params.mix));
+        self.frequency
+            .set_target(params.range.clamp(params.frequency));
+        self.rate.set_target(params.rate);
+        self.amount.set_target(params.amount);
+        self.drive.set_target(params.drive);
+    }
+
+    fn snap(&mut self) {
+        self.all_mut().into_iter().for_each(Smoother::snap);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        for smoother in self.all_mut() {
+            smoother.set_sample_rate(sample_rate);
+        }
+    }
+}
+
+/// the signals making up a block of output, like the hardware's Carrier Out and LFO Out jacks
+///
+/// every stem is sample aligned with the output, `output = dry * (1 - mix) + wet * mix`
+pub struct Stems<'a> {
+    /// the carrier of every channel, interleaved like the input
+    pub carrier: &'a mut [f32],
+    /// the LFO, one value per frame
+    pub lfo: &'a mut [f32],
+    /// the input after the drive stage, interleaved like the input
+    pub dry: &'a mut [f32],
+    /// the ring modulated input without any dry signal mixed in, interleaved like the input
+    pub wet: &'a mut [f32],
+}
@@ -0,0 +1,162 @@
+//! the built-in presets of classic MF-102 sounds
+
+use crate::params::{CarrierWaveform, FrequencyRange, Multiplier, RingModParams, Waveform};
+use crate::preset::Preset;
+
+/// a preset that ships with mf-102
+#[derive(Clone, Debug, PartialEq)]
+pub struct FactoryPreset {
+    /// the name it's selected by
+    pub name: &'static str,
+    /// what it sounds like
+    pub description: &'static str,
+    /// the knob positions
+    pub params: RingModParams,
+}
+
+impl FactoryPreset {
+    /// the factory preset as a preset of the current version
+    pub fn preset(&self) -> Preset {
+        Preset::new(self.params.clone(), Some(self.description.to_string()))
+    }
+}
+
+/// every factory preset
+pub const PRESETS: &[FactoryPreset] = &[
+    FactoryPreset {
+        name: "init",
+        description: "the knobs where mf-102 starts without a preset",
+        params: RingModParams::DEFAULT,
+    },
+    FactoryPreset {
+        name: "warble",
+        description: "a slow sine LFO gently bending a low carrier",
+        params: RingModParams {
+            drive: 0.0,
+            amount: 1.5,
+            lfo_waveform: Waveform::Sinusoidal,
+            rate: 0.4,
+            mix: 60,
+            frequency: 220.0,
+            range: FrequencyRange::Hi,
+            channel_phase_offset: 0.0,
+            ..RingModParams::DEFAULT
+        },
+    },
+    FactoryPreset {
+        name: "robot",
+        description: "a square LFO stepping the carrier between two pitches, fully wet",
+        params: RingModParams {
+            drive: 0.0,
+            amount: 3.3,
+            lfo_waveform: Waveform::Square,
+            lfo_slew: 6.0,
+            rate: 4.0,
+            mix: 100,
+            frequency: 110.0,
+            range: FrequencyRange::Hi,
+            channel_phase_offset: 0.0,
+            ..RingModParams::DEFAULT
+        },
+    },
+    FactoryPreset {
+        name: "tremolo",
+        description: "a LO range carrier chopping the amplitude without changing the pitch",
+        params: RingModParams {
+            drive: 0.0,
+            amount: 0.0,
+            lfo_waveform: Waveform::Sinusoidal,
+            rate: 0.1,
+            mix: 50,
+            frequency: 6.0,
+            range: FrequencyRange::Lo,
+            channel_phase_offset: 0.0,
+            ..RingModParams::DEFAULT
+        },
+    },
+    FactoryPreset {
+        name: "clang",
+        description: "a high static carrier for inharmonic, bell-like sidebands",
+        params: RingModParams {
+            drive: 0.0,
+            amount: 0.0,
+            lfo_waveform: Waveform::Sinusoidal,
+            rate: 0.1,
+            mix: 100,
+            frequency: 1150.0,
+            range: FrequencyRange::Hi,
+            channel_phase_offset: 0.0,
+            ..RingModParams::DEFAULT
+        },
+    },
+    FactoryPreset {
+        name: "swirl",
+        description: "a LO range carrier in quadrature between the channels, panning around",
+        params: RingModParams {
+            drive: 0.0,
+            amount: 2.0,
+            lfo_waveform: Waveform::Sinusoidal,
+            rate: 0.25,
+            mix: 80,
+            frequency: 1.5,
+            range: FrequencyRange::Lo,
+            channel_phase_offset: 90.0,
+            ..RingModParams::DEFAULT
+        },
+    },
+    FactoryPreset {
+        name: "fuzz",
+        description: "the input stage driven hard into a low carrier",
+        params: RingModParams {
+            drive: 7.0,
+            amount: 0.0,
+            lfo_waveform: Waveform::Square,
+            rate: 0.18,
+            mix: 85,
+            frequency: 75.0,
+            range: FrequencyRange::Hi,
+            channel_phase_offset: 0.0,
+            ..RingModParams::DEFAULT
+        },
+    },
+    FactoryPreset {
+        name: "vintage",
+        description: "a worn unit, its multiplier leaking and its oscillators drifting",
+        params: RingModParams {
+            drive: 1.5,
+            amount: 2.5,
+            lfo_waveform: Waveform::Sinusoidal,
+            rate: 0.6,
+            mix: 75,
+            frequency: 180.0,
+            range: FrequencyRange::Hi,
+            channel_phase_offset: 0.0,
+            multiplier: Multiplier::Analog,
+            vintage: true,
+            unit: 102,
+            drift: 15.0,
+            ..RingModParams::DEFAULT
+        },
+    },
+    FactoryPreset {
+        name: "buzz",
+        description: "a sawtooth carrier for a raspy, synth-like edge",
+        params: RingModParams {
+            drive: 0.0,
+            amount: 0.8,
+            lfo_waveform: Waveform::Triangle,
+            rate: 0.3,
+            mix: 90,
+            frequency: 330.0,
+            range: FrequencyRange::Hi,
+            channel_phase_offset: 0.0,
+            carrier_waveform: CarrierWaveform::Saw,
+            ..RingModParams::DEFAULT
+        },
+    },
+];
+
+/// the factory preset called `name`
+pub fn find(name: &str) -> Option<&'static FactoryPreset> {
+    PRESETS.iter().find(|preset| preset.name == name)
+}
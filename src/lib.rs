@@ -0,0 +1,42 @@
+//! Moogerfooger MF-102 ring modulator
+//!
+//! [`RingModulator`] processes interleaved, normalized frames block by block with the settings
+//! of [`RingModParams`], [`wav`] reads and writes WAV files in that format and [`realtime`]
+//! runs the ring modulator live through an audio device
+//!
+//! ```no_run
+//! use mf102::{RingModParams, RingModulator};
+//!
+//! let (spec, input) = mf102::wav::read("guitar.wav").unwrap();
+//! let mut output = vec![0.0; input.len()];
+//!
+//! RingModulator::new(RingModParams::default(), spec.sample_rate, spec.channels.into())
+//!     .process_block(&input, &mut output);
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod automation;
+pub mod carrier;
+pub mod clip;
+pub mod dither;
+mod drive;
+mod engine;
+pub mod factory;
+pub mod midi;
+mod multiplier;
+mod oscillator;
+mod oversample;
+mod params;
+pub mod preset;
+pub mod realtime;
+mod rng;
+pub mod smoothing;
+mod vintage;
+pub mod wav;
+
+pub use engine::{RingModulator, Stems};
+pub use params::{
+    CarrierWaveform, Feel, FrequencyRange, LfoModulation, LfoSync, Multiplier, NoteDivision,
+    RingModParams, Waveform,
+};
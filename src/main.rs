@@ -1,114 +1,1009 @@
-use hound::{WavReader, WavWriter};
-use std::f32::consts::PI;
-
-const RING_MOD_PARAMS: RingModParams = RingModParams {
-    mix: 71,
-    frequency: 156.0,
-    amount: 6.7,
-    lfo_waveform: Waveform::Square,
-    rate: 0.18,
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use hound::{SampleFormat, WavSpec};
+use mf102::automation::{Automation, ControlSource, Knob};
+use mf102::carrier::{CarrierEnd, ExternalCarrier};
+use mf102::clip::ClipMode;
+use mf102::dither::Dither;
+use mf102::factory;
+use mf102::midi::{self, MidiClock, MidiControl, MidiInput, Sequence, SequenceInput, Target};
+use mf102::preset::{Preset, PresetLibrary, UserConfig};
+use mf102::realtime::{self, AudioDevice, FileDevice};
+use mf102::smoothing::{Smoothing, SmoothingMode};
+use mf102::wav;
+use mf102::{
+    CarrierWaveform, FrequencyRange, LfoModulation, LfoSync, Multiplier, RingModParams,
+    RingModulator, Stems, Waveform,
 };
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-enum Waveform {
-    /// Sinusoidal LFO wave form will smoothly oscillate between 0-3 octaves above PARAMS.frequency
-    Sinusoidal,
-    /// Square LFO wave form instantaneously jumps between an unaffected carrier signal and 3 octaves above PARAMS.frequency
-    Square,
+/// Moogerfooger MF-102 ring modulator
+#[derive(Parser)]
+#[command(version, about, subcommand_negates_reqs = true)]
+struct Cli {
+    /// the WAV file to process, in real-time mode it stands in for the audio device's input
+    #[arg(required_unless_present = "realtime")]
+    input: Option<PathBuf>,
+    /// where to write the processed WAV file
+    #[arg(short, long, default_value = "output.wav")]
+    output: PathBuf,
+    /// bits per sample of the output, 8, 16, 24 or 32 [default: same as the input]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(8..=32))]
+    bits: Option<u16>,
+    /// sample format of the output, `int` or `float` [default: same as the input]
+    #[arg(long, value_parser = parse_sample_format)]
+    sample_format: Option<SampleFormat>,
+    /// how to keep the output within full scale, `hard`, `soft` or `limiter` (-1dBTP true-peak)
+    #[arg(long, default_value = "hard")]
+    clip: ClipMode,
+    /// dither used when quantizing to 24 bits or less, `none`, `tpdf` or `shaped`
+    #[arg(long, default_value = "tpdf")]
+    dither: Dither,
+
+    /// a WAV file replacing the internal carrier oscillator, like the Carrier In jack
+    #[arg(long, conflicts_with = "realtime")]
+    carrier: Option<PathBuf>,
+    /// what happens when the carrier file is shorter than the input, `loop` or `silence`
+    #[arg(long, default_value = "loop")]
+    carrier_end: CarrierEnd,
+    /// also write the carrier, LFO, dry and wet-only signals next to the output as
+    /// `<output>.carrier.wav`, `<output>.lfo.wav`, `<output>.dry.wav` and `<output>.wet.wav`,
+    /// hard clipped whatever `--clip` is
+    #[arg(long, conflicts_with = "realtime")]
+    stems: bool,
+
+    /// automate a knob (`mix`, `frequency`, `rate`, `amount` or `drive`) as `<KNOB>=<FILE>`,
+    /// either with a control rate WAV file treated as 0V to 5V CV, or with a JSON or CSV breakpoint envelope
+    #[arg(long, value_name = "KNOB=FILE", value_parser = parse_automation)]
+    automate: Vec<(Knob, PathBuf)>,
+
+    /// how automated knobs glide to new values, `one-pole` or `linear`
+    #[arg(long, default_value = "one-pole")]
+    smoothing: SmoothingMode,
+    /// in milliseconds, the one-pole time constant or the duration of a linear ramp
+    #[arg(long, default_value_t = 20.0, value_parser = parse_smoothing_time)]
+    smoothing_time: f32,
+
+    /// the tempo a `--sync`ed LFO follows, in beats per minute, the input starting on a bar
+    #[arg(long, default_value_t = 120.0, value_parser = parse_bpm)]
+    bpm: f64,
+    /// follow the tempo, start and song position of the MIDI clock on `--midi-in`
+    /// instead of `--bpm`
+    #[arg(long, requires = "midi_in")]
+    midi_clock: bool,
+
+    /// turn knobs and play the carrier with the MIDI of a Standard MIDI File, in time with the input
+    #[arg(long, value_name = "FILE", conflicts_with = "midi_in")]
+    midi_file: Option<PathBuf>,
+    /// the raw MIDI input port, like `hw:1,0,0`, turning knobs and playing the carrier in real-time mode
+    #[arg(long, value_name = "PORT", requires = "realtime")]
+    midi_in: Option<String>,
+    /// map a MIDI controller to a knob or switch (`mix`, `frequency`, `rate`, `amount`, `drive`,
+    /// `range` or `lfo-waveform`) as `<CC>=<TARGET>`
+    #[arg(long, value_name = "CC=TARGET", value_parser = parse_midi_cc)]
+    midi_cc: Vec<(u8, Target)>,
+    /// only listen to MIDI channel 1 to 16 [default: every channel]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=16))]
+    midi_channel: Option<u8>,
+    /// tune the carrier to the last key played, clamped to `--range`
+    #[arg(long)]
+    key_track: bool,
+    /// in milliseconds, how long the carrier takes to glide to a new frequency, in octaves
+    /// [default: `--smoothing-time` with `--smoothing`]
+    #[arg(long, value_parser = parse_smoothing_time)]
+    glide: Option<f32>,
+    /// map the next controllers moved on `--midi-in` to these targets, in order,
+    /// printing the `--midi-cc` of every one learned
+    #[arg(long, value_name = "TARGET", requires = "midi_in")]
+    midi_learn: Vec<Target>,
+
+    /// process a live input through an audio device instead of a file
+    #[arg(long)]
+    realtime: bool,
+    /// the audio device used in real-time mode, `null` is always silent
+    #[arg(long, default_value = "default")]
+    device: String,
+    /// frames per block in real-time mode
+    #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u32).range(16..=8192))]
+    buffer_size: u32,
+    /// sample rate of the audio device in real-time mode
+    #[arg(long, default_value_t = 48000, value_parser = clap::value_parser!(u32).range(1..))]
+    sample_rate: u32,
+    /// number of channels of the audio device in real-time mode
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    channels: u16,
+
+    /// a factory preset, a preset from the preset directory, or a `.toml` or `.json` preset file,
+    /// the knob flags override its settings
+    #[arg(long, value_name = "NAME|FILE")]
+    preset: Option<String>,
+    /// the user config supplying the defaults of the knobs and the preset directory
+    /// [default: `mf-102/config.toml` in the user's config directory]
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[command(flatten)]
+    knobs: KnobArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-struct RingModParams {
-    /// LFO section
-    /// 0 to 10, this is normalized and controls a percentage of a 3 octave jump
-    amount: f32,
-    /// the waveform for the carrier LFO modulation
-    lfo_waveform: Waveform,
-    /// 0.1Hz to 25Hz the rate of the LFO modulation on the carrier signal
-    rate: f32,
+/// the knobs and switches, anything that isn't given is taken from the preset or the user config
+#[derive(Args)]
+struct KnobArgs {
+    /// 0 to 10, input gain into the soft clipping input stage, 0 bypasses it [default: 0]
+    #[arg(long, value_parser = parse_drive)]
+    drive: Option<f32>,
+
+    /// 0 to 10, amount of the 3 octave LFO jump applied to the carrier [default: 6.7]
+    #[arg(long, value_parser = parse_amount)]
+    amount: Option<f32>,
+    /// the waveform for the carrier LFO modulation, `sine`, `square`, `triangle`, `ramp-up`,
+    /// `ramp-down`, `sample-and-hold` or `smooth-random` [default: square]
+    #[arg(long)]
+    lfo_waveform: Option<Waveform>,
+    /// 5 to 95 percent of a cycle the square LFO is high [default: 50]
+    #[arg(long, value_parser = parse_pulse_width)]
+    pulse_width: Option<f32>,
+    /// 0 to 100 ms the square LFO takes to swing between its levels, rounding the carrier's
+    /// jumps like the hardware's [default: 0]
+    #[arg(long, value_name = "MS", value_parser = parse_lfo_slew)]
+    lfo_slew: Option<f32>,
+    /// how the LFO bends the carrier, `octaves` sweeping up to 3 octaves above the frequency or
+    /// `linear` adding up to 3 times the frequency like older renders [default: octaves]
+    #[arg(long)]
+    lfo_modulation: Option<LfoModulation>,
+    /// 0.1Hz to 25Hz, rate of the LFO modulation [default: 0.18]
+    #[arg(long, value_parser = parse_rate)]
+    rate: Option<f32>,
+    /// lock the LFO to the tempo instead of `--rate`, a cycle lasting a note division like `1/4`,
+    /// `1/8t` (triplet), `1/8d` (dotted) or `2/1` (two bars), or `free` [default: free]
+    #[arg(long, value_name = "DIVISION")]
+    sync: Option<LfoSync>,
 
-    /// Modulator section
-    /// 0 to 100, mix with the original sampled signal
-    mix: u8,
-    /// 0.6Hz to 80Hz (LO setting), 30Hz to 4kHz (HI setting) for the carrier signal
-    frequency: f32,
+    /// 0 to 100, mix with the original sampled signal [default: 71]
+    #[arg(long, value_parser = parse_mix)]
+    mix: Option<u8>,
+    /// frequency of the carrier signal in Hz, must be within `--range` [default: 156, clamped to the range]
+    #[arg(long, conflicts_with = "frequency_knob")]
+    frequency: Option<f32>,
+    /// 0 to 10, frequency knob position mapped onto `--range` like the hardware dial
+    #[arg(long, value_parser = parse_frequency_knob)]
+    frequency_knob: Option<f32>,
+    /// the carrier frequency range, `lo` (0.6Hz to 80Hz) or `hi` (30Hz to 4kHz) [default: hi]
+    #[arg(long)]
+    range: Option<FrequencyRange>,
+    /// 0 to 180 degrees, carrier phase offset between channels for stereo width [default: 0]
+    #[arg(long, value_parser = parse_channel_phase_offset)]
+    channel_phase_offset: Option<f32>,
+    /// the shape of the carrier, `sine`, `triangle`, `square`, `saw` or `pulse`, all but the sine
+    /// band-limited [default: sine]
+    #[arg(long)]
+    carrier_waveform: Option<CarrierWaveform>,
+    /// 5 to 95 percent of a cycle the pulse carrier is high [default: 25]
+    #[arg(long, value_parser = parse_pulse_width)]
+    carrier_pulse_width: Option<f32>,
+
+    /// the multiplier, `ideal` or `analog` with the imperfections below [default: ideal]
+    #[arg(long)]
+    multiplier: Option<Multiplier>,
+    /// 0 to 10 percent of the carrier leaking through the analog multiplier [default: 0.8]
+    #[arg(long, value_parser = parse_leak)]
+    carrier_bleed: Option<f32>,
+    /// 0 to 10 percent of the input leaking through the analog multiplier [default: 0.5]
+    #[arg(long, value_parser = parse_leak)]
+    signal_feedthrough: Option<f32>,
+    /// 0 to 1, how hard the analog multiplier compresses the input and the carrier [default: 0.25]
+    #[arg(long, value_parser = parse_nonlinearity)]
+    nonlinearity: Option<f32>,
+    /// run the multiplier at 2, 4 or 8 times the sample rate so the sidebands above nyquist don't
+    /// fold back, adding some latency, 1 turning it off [default: 1]
+    #[arg(long, value_name = "FACTOR", value_parser = parse_oversampling)]
+    oversampling: Option<u32>,
+
+    /// model an aging unit, with drifting oscillators and the tolerances of `--unit` [default: false]
+    #[arg(long, num_args = 0..=1, default_missing_value = "true", value_name = "BOOL")]
+    vintage: Option<bool>,
+    /// the unit whose component tolerances `--vintage` models, any number picking its own
+    /// consistent set [default: 0]
+    #[arg(long)]
+    unit: Option<u32>,
+    /// 0 to 50 cents the oscillators drift either way with `--vintage` [default: 8]
+    #[arg(long, value_parser = parse_drift)]
+    drift: Option<f32>,
 }
 
-fn ring_mod(
-    sample_rate: u32,
-    sample_length: usize,
-    signal: impl IntoIterator<Item = i32>,
-    params: &RingModParams,
-) -> Vec<i32> {
-    let mut res = vec![];
+impl KnobArgs {
+    /// `params` with the knobs that were given turned
+    fn apply(&self, params: RingModParams) -> Result<RingModParams, String> {
+        let range = self.range.unwrap_or(params.range);
 
-    // normalized mix and amount parameter
-    let mix = f32::from(params.mix) / 100.0;
-    let amount = params.amount / 10.0;
+        let frequency = match (self.frequency, self.frequency_knob) {
+            (_, Some(knob)) => range.knob_to_frequency(knob),
+            (Some(frequency), None) if range.contains(frequency) => frequency,
+            (Some(frequency), None) => {
+                let (min, max) = range.bounds();
+                return Err(format!(
+                    "invalid value '{frequency}' for '--frequency': not in {min}..={max} for the selected range"
+                ));
+            }
+            (None, None) => range.clamp(params.frequency),
+        };
 
-    // the signal
-    let mut signal_iter = signal.into_iter();
+        Ok(RingModParams {
+            drive: self.drive.unwrap_or(params.drive),
+            amount: self.amount.unwrap_or(params.amount),
+            lfo_waveform: self.lfo_waveform.unwrap_or(params.lfo_waveform),
+            pulse_width: self.pulse_width.unwrap_or(params.pulse_width),
+            lfo_slew: self.lfo_slew.unwrap_or(params.lfo_slew),
+            lfo_modulation: self.lfo_modulation.unwrap_or(params.lfo_modulation),
+            rate: self.rate.unwrap_or(params.rate),
+            sync: self.sync.unwrap_or(params.sync),
+            mix: self.mix.unwrap_or(params.mix),
+            frequency,
+            range,
+            channel_phase_offset: self
+                .channel_phase_offset
+                .unwrap_or(params.channel_phase_offset),
+            carrier_waveform: self.carrier_waveform.unwrap_or(params.carrier_waveform),
+            carrier_pulse_width: self
+                .carrier_pulse_width
+                .unwrap_or(params.carrier_pulse_width),
+            multiplier: self.multiplier.unwrap_or(params.multiplier),
+            carrier_bleed: self.carrier_bleed.unwrap_or(params.carrier_bleed),
+            signal_feedthrough: self.signal_feedthrough.unwrap_or(params.signal_feedthrough),
+            nonlinearity: self.nonlinearity.unwrap_or(params.nonlinearity),
+            oversampling: self.oversampling.unwrap_or(params.oversampling),
+            vintage: self.vintage.unwrap_or(params.vintage),
+            unit: self.unit.unwrap_or(params.unit),
+            drift: self.drift.unwrap_or(params.drift),
+        })
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// manage the presets in the preset directory
+    #[command(subcommand)]
+    Preset(PresetCommand),
+}
 
-    let mut lfo_phase = 0.0;
-    let mut carrier_phase = 0.0;
+#[derive(Subcommand)]
+enum PresetCommand {
+    /// list the factory and the saved presets
+    List,
+    /// print a preset's settings, migrated to the current version
+    Show {
+        /// a user or factory preset, or a `.toml` or `.json` preset file
+        #[arg(value_name = "NAME|FILE")]
+        preset: String,
+    },
+    /// save the knob flags, on top of the user config's defaults or an inherited preset, as a preset
+    Save {
+        /// the preset's name in the preset directory, naming it after a factory preset overrides it
+        name: String,
+        /// a user or factory preset to base it on, only the knobs that differ from it are saved
+        #[arg(long, value_name = "NAME")]
+        inherits: Option<String>,
+        /// what the preset sounds like
+        #[arg(long)]
+        description: Option<String>,
+        /// replace an existing preset of the same name
+        #[arg(long)]
+        force: bool,
+        #[command(flatten)]
+        knobs: KnobArgs,
+    },
+}
 
-    let lfo_increment = 2.0 * PI * params.rate / sample_rate as f32;
+impl Cli {
+    /// the output format, taking anything that isn't overridden from the input's `spec`
+    fn output_spec(&self, spec: WavSpec) -> WavSpec {
+        let sample_format = match (self.sample_format, self.bits) {
+            (Some(sample_format), _) => sample_format,
+            // float samples only come in 32-bit
+            (None, Some(bits)) if bits != 32 => SampleFormat::Int,
+            (None, _) => spec.sample_format,
+        };
+        let bits_per_sample = match (sample_format, self.bits) {
+            (SampleFormat::Float, _) => 32,
+            (SampleFormat::Int, Some(bits)) => bits,
+            // float input has no integer bit depth to carry over
+            (SampleFormat::Int, None) if spec.sample_format == SampleFormat::Float => 24,
+            (SampleFormat::Int, None) => spec.bits_per_sample,
+        };
 
-    for _ in 0..sample_length {
-        lfo_phase = (lfo_phase + lfo_increment).rem_euclid(2.0 * PI);
+        WavSpec {
+            sample_format,
+            bits_per_sample,
+            ..spec
+        }
+    }
 
-        let lfo = match params.lfo_waveform {
-            Waveform::Sinusoidal => lfo_phase.sin(),
-            Waveform::Square => {
-                if lfo_phase.sin() >= 0.0 {
-                    1.0
+    /// the knobs as set by the flags, then the preset and then the user config
+    fn params(&self, config: &UserConfig) -> Result<RingModParams, String> {
+        match (self.sample_format, self.bits) {
+            (Some(SampleFormat::Float), Some(bits)) if bits != 32 => {
+                return Err(format!(
+                    "invalid value '{bits}' for '--bits': float output must be 32-bit"
+                ));
+            }
+            (_, Some(bits)) if ![8, 16, 24, 32].contains(&bits) => {
+                return Err(format!(
+                    "invalid value '{bits}' for '--bits': expected 8, 16, 24 or 32"
+                ));
+            }
+            _ => {}
+        }
+
+        let params = match &self.preset {
+            Some(preset) => load_preset(&config.presets(), preset)?.params,
+            None => config.defaults.clone(),
+        };
+
+        self.knobs.apply(params)
+    }
+
+    fn config(&self) -> Result<UserConfig, String> {
+        match &self.config {
+            Some(path) => UserConfig::load(path),
+            None => UserConfig::load_default(),
+        }
+    }
+}
+
+/// loads a `.toml` or `.json` file, or a user or factory preset
+fn load_preset(library: &PresetLibrary, preset: &str) -> Result<Preset, String> {
+    let path = Path::new(preset);
+
+    if matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("toml" | "json")
+    ) && path.is_file()
+    {
+        library.load_file(path)
+    } else {
+        library.load(preset)
+    }
+}
+
+fn run_preset_command(config: &UserConfig, command: &PresetCommand) -> Result<(), String> {
+    let library = config.presets();
+
+    match command {
+        PresetCommand::List => {
+            let user = library.list()?;
+
+            for preset in factory::PRESETS {
+                let overridden = if user.iter().any(|name| name == preset.name) {
+                    ", overridden"
                 } else {
-                    0.0
+                    ""
+                };
+
+                println!(
+                    "{}\t(factory{overridden})\t{}",
+                    preset.name, preset.description
+                );
+            }
+
+            for name in &user {
+                match library.load(name) {
+                    Ok(preset) => {
+                        println!("{name}\t(user)\t{}", preset.description.unwrap_or_default())
+                    }
+                    Err(e) => eprintln!("warning: {e}"),
                 }
             }
+
+            Ok(())
+        }
+        PresetCommand::Show { preset } => {
+            print!("{}", load_preset(&library, preset)?.to_toml());
+            Ok(())
+        }
+        PresetCommand::Save {
+            name,
+            inherits,
+            description,
+            force,
+            knobs,
+        } => {
+            if !force && library.find(name).is_some() {
+                return Err(format!(
+                    "there already is a preset called `{name}`, use `--force` to replace it"
+                ));
+            }
+
+            let preset = match inherits {
+                Some(inherits) => Preset {
+                    inherits: Some(inherits.clone()),
+                    ..Preset::new(
+                        knobs.apply(library.load(inherits)?.params)?,
+                        description.clone(),
+                    )
+                },
+                None => Preset::new(knobs.apply(config.defaults.clone())?, description.clone()),
+            };
+
+            let path = library.save(name, &preset)?;
+            eprintln!("saved `{}`", path.display());
+
+            Ok(())
+        }
+    }
+}
+
+fn parse_sample_format(s: &str) -> Result<SampleFormat, String> {
+    match s {
+        "int" => Ok(SampleFormat::Int),
+        "float" => Ok(SampleFormat::Float),
+        _ => Err(format!(
+            "unknown sample format `{s}`, expected `int` or `float`"
+        )),
+    }
+}
+
+fn parse_smoothing_time(s: &str) -> Result<f32, String> {
+    parse_knob(s, 0.0..=10000.0)
+}
+
+fn parse_midi_cc(s: &str) -> Result<(u8, Target), String> {
+    let (controller, target) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected `<CC>=<TARGET>`, got `{s}`"))?;
+
+    let controller = match controller.parse() {
+        Ok(controller @ 0..=127) => controller,
+        _ => {
+            return Err(format!(
+                "`{controller}` isn't a controller, expected 0 to 127"
+            ))
+        }
+    };
+
+    Ok((controller, target.parse()?))
+}
+
+fn parse_automation(s: &str) -> Result<(Knob, PathBuf), String> {
+    let (knob, path) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected `<KNOB>=<FILE>`, got `{s}`"))?;
+
+    Ok((knob.parse()?, PathBuf::from(path)))
+}
+
+/// parses an `f32` knob value and checks that it's within `range`, like a preset's are
+fn parse_knob(s: &str, range: RangeInclusive<f32>) -> Result<f32, String> {
+    let value: f32 = s.parse().map_err(|_| format!("`{s}` isn't a number"))?;
+
+    RingModParams::in_range(value, &range)
+}
+
+fn parse_drive(s: &str) -> Result<f32, String> {
+    parse_knob(s, RingModParams::DRIVE)
+}
+
+fn parse_amount(s: &str) -> Result<f32, String> {
+    parse_knob(s, RingModParams::AMOUNT)
+}
+
+fn parse_pulse_width(s: &str) -> Result<f32, String> {
+    parse_knob(s, RingModParams::PULSE_WIDTH)
+}
+
+fn parse_lfo_slew(s: &str) -> Result<f32, String> {
+    parse_knob(s, RingModParams::LFO_SLEW)
+}
+
+fn parse_oversampling(s: &str) -> Result<u32, String> {
+    match s.parse() {
+        Ok(factor) if RingModParams::OVERSAMPLING.contains(&factor) => Ok(factor),
+        _ => Err(format!("`{s}` isn't 1, 2, 4 or 8")),
+    }
+}
+
+fn parse_mix(s: &str) -> Result<u8, String> {
+    let mix = s
+        .parse()
+        .map_err(|_| format!("`{s}` isn't a whole number"))?;
+
+    RingModParams::in_range(mix, &RingModParams::MIX)
+}
+
+fn parse_rate(s: &str) -> Result<f32, String> {
+    parse_knob(s, RingModParams::RATE)
+}
+
+fn parse_leak(s: &str) -> Result<f32, String> {
+    parse_knob(s, RingModParams::LEAK)
+}
+
+fn parse_nonlinearity(s: &str) -> Result<f32, String> {
+    parse_knob(s, RingModParams::NONLINEARITY)
+}
+
+fn parse_drift(s: &str) -> Result<f32, String> {
+    parse_knob(s, RingModParams::DRIFT)
+}
+
+fn parse_bpm(s: &str) -> Result<f64, String> {
+    let bpm: f64 = s.parse().map_err(|_| format!("`{s}` isn't a number"))?;
+
+    if (1.0..=999.0).contains(&bpm) {
+        Ok(bpm)
+    } else {
+        Err(format!("{bpm} is not in 1..=999"))
+    }
+}
+
+fn parse_frequency_knob(s: &str) -> Result<f32, String> {
+    parse_knob(s, 0.0..=10.0)
+}
+
+fn parse_channel_phase_offset(s: &str) -> Result<f32, String> {
+    parse_knob(s, RingModParams::CHANNEL_PHASE_OFFSET)
+}
+
+/// writes the processed `samples` of an input laid out as described by `spec`
+fn write_output(cli: &Cli, spec: WavSpec, samples: &[f32]) -> Result<(), String> {
+    let clipped = wav::write(
+        &cli.output,
+        cli.output_spec(spec),
+        samples,
+        cli.clip,
+        cli.dither,
+    )
+    .map_err(|e| format!("couldn't write `{}`: {e}", cli.output.display()))?;
+
+    report_clipped(&cli.output, clipped);
+
+    Ok(())
+}
+
+fn report_clipped(path: &Path, clipped: usize) {
+    if clipped > 0 {
+        eprintln!(
+            "warning: {clipped} samples of `{}` were over full scale",
+            path.display()
+        );
+    }
+}
+
+/// `<output>.<stem>.wav` next to `output`
+fn stem_path(output: &Path, stem: &str) -> PathBuf {
+    let name = output.file_stem().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!("{name}.{stem}.wav"))
+}
+
+/// renders the stems of the output into their own files
+struct StemWriters {
+    channels: usize,
+    carrier: Vec<f32>,
+    lfo: Vec<f32>,
+    dry: Vec<f32>,
+    wet: Vec<f32>,
+    /// the carrier, LFO, dry and wet stem's files
+    writers: Vec<(PathBuf, wav::Writer)>,
+}
+
+impl StemWriters {
+    fn create(cli: &Cli, spec: WavSpec) -> Result<Self, String> {
+        let spec = cli.output_spec(spec);
+        // the LFO is the same for every channel
+        let lfo_spec = WavSpec {
+            channels: 1,
+            ..spec
         };
 
-        // the carrier signal that's applied to the sampled one
-        let carrier_increment =
-            2.0 * PI * (params.frequency + lfo * (params.frequency * 3.0 * amount))
-                / sample_rate as f32;
+        let writers = [
+            ("carrier", spec),
+            ("lfo", lfo_spec),
+            ("dry", spec),
+            ("wet", spec),
+        ]
+        .into_iter()
+        .map(|(stem, spec)| {
+            let path = stem_path(&cli.output, stem);
+            // clamped rather than shaped like the output, so the stems still add up to it
+            let writer = wav::Writer::create(&path, spec, ClipMode::Hard, cli.dither)
+                .map_err(|e| format!("couldn't create `{}`: {e}", path.display()))?;
+
+            Ok((path, writer))
+        })
+        .collect::<Result<_, String>>()?;
+
+        Ok(Self {
+            channels: usize::from(spec.channels),
+            carrier: vec![],
+            lfo: vec![],
+            dry: vec![],
+            wet: vec![],
+            writers,
+        })
+    }
+
+    fn process(
+        &mut self,
+        modulator: &mut RingModulator,
+        input: &[f32],
+        carrier: Option<&[f32]>,
+        output: &mut [f32],
+    ) -> Result<(), String> {
+        self.carrier.resize(input.len(), 0.0);
+        self.lfo.resize(input.len() / self.channels, 0.0);
+        self.dry.resize(input.len(), 0.0);
+        self.wet.resize(input.len(), 0.0);
+
+        modulator.process_block_with_stems(
+            input,
+            carrier,
+            output,
+            &mut Stems {
+                carrier: &mut self.carrier,
+                lfo: &mut self.lfo,
+                dry: &mut self.dry,
+                wet: &mut self.wet,
+            },
+        );
+
+        let stems = [&self.carrier, &self.lfo, &self.dry, &self.wet];
+
+        for ((path, writer), stem) in self.writers.iter_mut().zip(stems) {
+            writer
+                .write(stem)
+                .map_err(|e| format!("couldn't write `{}`: {e}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    fn finalize(self) -> Result<(), String> {
+        for (path, writer) in self.writers {
+            let clipped = writer
+                .finalize()
+                .map_err(|e| format!("couldn't write `{}`: {e}", path.display()))?;
+
+            report_clipped(&path, clipped);
+        }
+
+        Ok(())
+    }
+}
+
+fn read_input(input: &PathBuf) -> Result<(WavSpec, Vec<f32>), String> {
+    wav::read(input).map_err(|e| format!("couldn't read `{}`: {e}", input.display()))
+}
 
-        carrier_phase = (carrier_phase + carrier_increment).rem_euclid(2.0 * PI);
+/// frames read, processed and written at a time when processing a file
+const CHUNK_SIZE: usize = 4096;
 
-        let carrier = carrier_phase.sin();
+fn run(cli: &Cli, input: &PathBuf, params: &RingModParams) -> Result<(), String> {
+    let mut params = params.clone();
+    let read_err = |e| format!("couldn't read `{}`: {e}", input.display());
+    let write_err = |e| format!("couldn't write `{}`: {e}", cli.output.display());
 
-        if let Some(sample) = signal_iter.next() {
-            let sample = sample as f32;
+    let mut reader = wav::Reader::open(input).map_err(read_err)?;
+    let spec = reader.spec();
+    let channels = usize::from(spec.channels);
 
-            // accounted for the mix parameter
-            // see https://en.wikipedia.org/wiki/Ring_modulation#Simplified_operation
-            let out_sample = (sample * (1.0 - mix)) + (sample * carrier * mix);
+    if !reader.len().is_multiple_of(channels) {
+        println!("Signal processing may be incomplete");
+    }
 
-            res.push(out_sample as i32);
-        } else {
-            println!("Signal processing may be incomplete");
+    let mut writer = wav::Writer::create(&cli.output, cli.output_spec(spec), cli.clip, cli.dither)
+        .map_err(write_err)?;
+    let mut modulator = modulator(cli, &params, spec.sample_rate, channels)?;
+
+    // the output isn't moved back, it's as late as it would be live
+    if modulator.latency() > 0.0 {
+        let frames = modulator.latency();
+        let ms = frames * 1000.0 / spec.sample_rate as f32;
+
+        eprintln!("latency: {frames:.1} frames ({ms:.2}ms)");
+    }
+
+    let mut carrier = match &cli.carrier {
+        Some(path) => {
+            let (carrier_spec, signal) = read_input(path)?;
+
+            Some(ExternalCarrier::new(
+                &signal,
+                carrier_spec.sample_rate,
+                usize::from(carrier_spec.channels),
+                spec.sample_rate,
+                channels,
+                cli.carrier_end,
+            ))
+        }
+        None => None,
+    };
+
+    let mut stems = if cli.stems {
+        Some(StemWriters::create(cli, spec)?)
+    } else {
+        None
+    };
+
+    let mut midi = match &cli.midi_file {
+        Some(path) => {
+            let sequence = Sequence::load(path)
+                .map_err(|e| format!("couldn't read `{}`: {e}", path.display()))?;
+
+            Some((sequence, midi_control(cli)))
+        }
+        None => None,
+    };
+    let mut next_message = 0;
+    // frames processed before the current chunk
+    let mut position = 0;
+
+    let mut input = vec![0.0; CHUNK_SIZE * channels];
+    let mut carrier_input = vec![0.0; CHUNK_SIZE * channels];
+    let mut output = vec![0.0; CHUNK_SIZE * channels];
+
+    loop {
+        let read = reader.read(&mut input).map_err(read_err)?;
+        // only whole frames are processed
+        let read = read - read % channels;
+
+        if read == 0 {
             break;
         }
+
+        if let Some(carrier) = &mut carrier {
+            carrier.fill(&mut carrier_input[..read]);
+        }
+
+        let frames = read / channels;
+        let mut start = 0;
+
+        // the chunk is split at every MIDI message so it's applied on the frame it's at
+        while start < frames {
+            let mut end = frames;
+
+            if let Some((sequence, control)) = &mut midi {
+                for &(time, message) in &sequence.events()[next_message..] {
+                    let frame = (time * f64::from(spec.sample_rate)).round() as usize;
+
+                    if frame > position + start {
+                        end = end.min(frame - position);
+                        break;
+                    }
+
+                    control.handle(message, &mut params);
+                    next_message += 1;
+                }
+
+                modulator.set_params(params.clone());
+            }
+
+            let samples = start * channels..end * channels;
+            let input = &input[samples.clone()];
+            let output = &mut output[samples.clone()];
+            let external = carrier.as_ref().map(|_| &carrier_input[samples]);
+
+            match (&mut stems, external) {
+                (Some(stems), external) => {
+                    stems.process(&mut modulator, input, external, output)?
+                }
+                (None, Some(external)) => {
+                    modulator.process_block_with_carrier(input, external, output)
+                }
+                (None, None) => modulator.process_block(input, output),
+            }
+
+            start = end;
+        }
+
+        position += frames;
+
+        writer.write(&output[..read]).map_err(write_err)?;
+    }
+
+    report_clipped(&cli.output, writer.finalize().map_err(write_err)?);
+
+    if let Some(stems) = stems {
+        stems.finalize()?;
+    }
+
+    Ok(())
+}
+
+/// a ring modulator set up with the automation and smoothing of `cli`
+fn modulator(
+    cli: &Cli,
+    params: &RingModParams,
+    sample_rate: u32,
+    channels: usize,
+) -> Result<RingModulator, String> {
+    // the input stage stays in the signal path if the drive can be turned, so the latency never changes
+    let drive = Target::Knob(Knob::Drive);
+    let mut modulator = if cli.automate.iter().any(|(knob, _)| *knob == Knob::Drive)
+        || cli.midi_cc.iter().any(|(_, target)| *target == drive)
+        || cli.midi_learn.contains(&drive)
+    {
+        RingModulator::with_drive_stage(params.clone(), sample_rate, channels)
+    } else {
+        RingModulator::new(params.clone(), sample_rate, channels)
+    };
+    let smoothing = Smoothing {
+        mode: cli.smoothing,
+        time: cli.smoothing_time / 1000.0,
+    };
+
+    for knob in [
+        Knob::Mix,
+        Knob::Frequency,
+        Knob::Rate,
+        Knob::Amount,
+        Knob::Drive,
+    ] {
+        modulator.set_smoothing(knob, smoothing);
+    }
+
+    // a constant time portamento, however far apart the keys are
+    if let Some(glide) = cli.glide {
+        modulator.set_smoothing(
+            Knob::Frequency,
+            Smoothing {
+                mode: SmoothingMode::Linear,
+                time: glide / 1000.0,
+            },
+        );
+    }
+
+    modulator.set_tempo(cli.bpm);
+    modulator.set_automation(load_automation(cli)?);
+
+    Ok(modulator)
+}
+
+fn midi_control(cli: &Cli) -> MidiControl {
+    let mut control = MidiControl::default();
+
+    control.set_channel(cli.midi_channel.map(|channel| channel - 1));
+    control.set_key_tracking(cli.key_track);
+
+    for &(controller, target) in &cli.midi_cc {
+        control.map(controller, target);
+    }
+
+    control.learn(cli.midi_learn.iter().copied());
+
+    control
+}
+
+fn prompt_learn(target: Option<Target>) {
+    if let Some(target) = target {
+        eprintln!("move the MIDI controller for `{target}`");
+    }
+}
+
+/// processes everything the device captures, with the MIDI of `--midi-in` or `--midi-file`
+fn run_device(
+    cli: &Cli,
+    device: &mut dyn AudioDevice,
+    modulator: &mut RingModulator,
+) -> Result<(), String> {
+    let mut midi: Box<dyn MidiInput> = match (&cli.midi_in, &cli.midi_file) {
+        (Some(port), _) => midi::open(port)?,
+        (None, Some(path)) => {
+            let sequence = Sequence::load(path)
+                .map_err(|e| format!("couldn't read `{}`: {e}", path.display()))?;
+
+            Box::new(SequenceInput::new(
+                sequence,
+                device.sample_rate(),
+                device.buffer_size(),
+            ))
+        }
+        (None, None) => return realtime::run(device, modulator),
+    };
+
+    let mut control = midi_control(cli);
+    let mut clock = cli.midi_clock.then(|| MidiClock::new(device.sample_rate()));
+    prompt_learn(control.learning());
+
+    realtime::run_with_midi(
+        device,
+        modulator,
+        midi.as_mut(),
+        &mut control,
+        clock.as_mut(),
+        |(controller, target), next| {
+            eprintln!("learned `--midi-cc {controller}={target}`");
+            prompt_learn(next);
+        },
+    )
+}
+
+fn load_automation(cli: &Cli) -> Result<Automation, String> {
+    let mut automation = Automation::default();
+
+    for (knob, path) in &cli.automate {
+        let source = ControlSource::load(path)
+            .map_err(|e| format!("couldn't load automation `{}`: {e}", path.display()))?;
+
+        automation.add(*knob, source);
     }
 
-    res
+    Ok(automation)
 }
 
-fn main() {
-    let r = WavReader::open("guitar.wav").unwrap();
-    let mut w = WavWriter::create("output.wav", r.spec()).unwrap();
+fn report_latency(device: &dyn AudioDevice, modulator: &RingModulator) {
+    let frames = device.latency() as f32 + modulator.latency();
+    let ms = frames * 1000.0 / device.sample_rate() as f32;
 
-    // total number of samples in the input file "guitar.wav"
-    let len = r.len();
-    let sample_rate = r.spec().sample_rate;
+    eprintln!(
+        "{} frames per block at {}Hz, latency: {frames:.1} frames ({ms:.2}ms)",
+        device.buffer_size(),
+        device.sample_rate()
+    );
+}
 
-    // the actual signal
-    let signal = r
-        .into_samples()
-        .map(|sample| sample.expect("Failed to open signal as an array"))
-        .collect::<Vec<i32>>();
+fn run_realtime(cli: &Cli, params: &RingModParams) -> Result<(), String> {
+    let buffer_size = cli.buffer_size as usize;
 
-    let ring_mod_result = ring_mod(sample_rate, len as usize, signal, &RING_MOD_PARAMS);
+    match &cli.input {
+        // the file stands in for the device, and what it plays back is written to the output
+        Some(input) => {
+            let (spec, signal) = read_input(input)?;
+            let channels = usize::from(spec.channels);
 
-    for sample in ring_mod_result {
-        w.write_sample(sample).unwrap();
+            let mut device = FileDevice::new(signal, spec.sample_rate, channels, buffer_size);
+            let mut modulator = modulator(cli, params, spec.sample_rate, channels)?;
+
+            report_latency(&device, &modulator);
+            run_device(cli, &mut device, &mut modulator)?;
+
+            write_output(cli, spec, &device.into_output())
+        }
+        None => {
+            let mut device = realtime::open(
+                &cli.device,
+                cli.sample_rate,
+                usize::from(cli.channels),
+                buffer_size,
+            )?;
+            let mut modulator = modulator(cli, params, device.sample_rate(), device.channels())?;
+
+            report_latency(device.as_ref(), &modulator);
+            run_device(cli, device.as_mut(), &mut modulator)
+        }
+    }
+}
+
+fn run_file_or_realtime(cli: &Cli, params: &RingModParams) -> Result<(), String> {
+    match &cli.input {
+        _ if cli.realtime => run_realtime(cli, params),
+        Some(input) => run(cli, input, params),
+        None => unreachable!("clap requires an input outside of real-time mode"),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let res = cli.config().and_then(|config| match &cli.command {
+        Some(Command::Preset(command)) => run_preset_command(&config, command),
+        None => {
+            let params = cli
+                .params(&config)
+                .unwrap_or_else(|e| Cli::command().error(ErrorKind::ValueValidation, e).exit());
+
+            run_file_or_realtime(&cli, &params)
+        }
+    });
+
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
     }
 }
@@ -0,0 +1,524 @@
+//! MIDI control of the knobs and keyboard tracking of the carrier, live or from a Standard MIDI File
+
+use crate::automation::Knob;
+use crate::params::{FrequencyRange, RingModParams, Waveform};
+use crate::RingModulator;
+use midly::live::{LiveEvent, SystemCommon, SystemRealtime};
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::str::FromStr;
+use std::{fmt, fs};
+
+/// tempo of a Standard MIDI File until it sets one, in microseconds per beat
+const DEFAULT_TEMPO: f64 = 500_000.0;
+
+/// MIDI clock ticks a beat
+const TICKS_PER_BEAT: usize = 24;
+
+/// the MIDI messages that control the ring modulator, channels are 0 to 15
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// a key was pressed
+    NoteOn {
+        /// 0 to 15
+        channel: u8,
+        /// 60 being middle C
+        key: u8,
+        /// 1 to 127
+        velocity: u8,
+    },
+    /// a key was released, also sent as a note on with a velocity of 0
+    NoteOff {
+        /// 0 to 15
+        channel: u8,
+        /// 60 being middle C
+        key: u8,
+    },
+    /// a controller was moved
+    ControlChange {
+        /// 0 to 15
+        channel: u8,
+        /// 0 to 127
+        controller: u8,
+        /// 0 to 127
+        value: u8,
+    },
+    /// a MIDI clock tick, 24 to a beat
+    Clock,
+    /// the song was started from its beginning
+    Start,
+    /// the song carried on from where it was stopped
+    Continue,
+    /// the song was stopped
+    Stop,
+    /// the song moved
+    SongPosition {
+        /// sixteenth notes since the start of the song
+        sixteenths: u16,
+    },
+}
+
+impl Message {
+    fn from_midi(channel: u8, message: MidiMessage) -> Option<Self> {
+        match message {
+            MidiMessage::NoteOn { key, vel } if vel == 0 => Some(Self::NoteOff {
+                channel,
+                key: key.as_int(),
+            }),
+            MidiMessage::NoteOn { key, vel } => Some(Self::NoteOn {
+                channel,
+                key: key.as_int(),
+                velocity: vel.as_int(),
+            }),
+            MidiMessage::NoteOff { key, .. } => Some(Self::NoteOff {
+                channel,
+                key: key.as_int(),
+            }),
+            MidiMessage::Controller { controller, value } => Some(Self::ControlChange {
+                channel,
+                controller: controller.as_int(),
+                value: value.as_int(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn from_live(event: LiveEvent) -> Option<Self> {
+        match event {
+            LiveEvent::Midi { channel, message } => Self::from_midi(channel.as_int(), message),
+            LiveEvent::Common(SystemCommon::SongPosition(position)) => Some(Self::SongPosition {
+                sixteenths: position.as_int(),
+            }),
+            LiveEvent::Realtime(SystemRealtime::TimingClock) => Some(Self::Clock),
+            LiveEvent::Realtime(SystemRealtime::Start) => Some(Self::Start),
+            LiveEvent::Realtime(SystemRealtime::Continue) => Some(Self::Continue),
+            LiveEvent::Realtime(SystemRealtime::Stop) => Some(Self::Stop),
+            _ => None,
+        }
+    }
+
+    /// parses a single message with its status byte, `None` for messages that don't control anything
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        Self::from_live(LiveEvent::parse(bytes).ok()?)
+    }
+
+    /// the channel the message was sent on, `None` for system messages
+    pub fn channel(self) -> Option<u8> {
+        match self {
+            Self::NoteOn { channel, .. }
+            | Self::NoteOff { channel, .. }
+            | Self::ControlChange { channel, .. } => Some(channel),
+            Self::Clock | Self::Start | Self::Continue | Self::Stop | Self::SongPosition { .. } => {
+                None
+            }
+        }
+    }
+}
+
+/// the frequency of a key in Hz, in equal temperament with A4 at 440Hz
+pub fn key_to_frequency(key: u8) -> f32 {
+    440.0 * 2f32.powf((f32::from(key) - 69.0) / 12.0)
+}
+
+/// what a controller can be mapped to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// sweeps a knob across its travel
+    Knob(Knob),
+    /// the LO/HI switch, HI from a value of 64 on
+    Range,
+    /// the LFO waveform, the controller's travel split evenly between the waveforms
+    LfoWaveform,
+}
+
+impl Target {
+    fn apply(self, value: u8, params: &mut RingModParams) {
+        match self {
+            Self::Knob(knob) => {
+                let position = f32::from(value) / 127.0;
+                knob.set(params, knob.position_to_value(position, params.range));
+            }
+            Self::Range => {
+                params.range = if value >= 64 {
+                    FrequencyRange::Hi
+                } else {
+                    FrequencyRange::Lo
+                }
+            }
+            Self::LfoWaveform => {
+                let waveform = usize::from(value) * Waveform::ALL.len() / 128;
+                params.lfo_waveform = Waveform::ALL[waveform];
+            }
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Knob(knob) => knob.fmt(f),
+            Self::Range => f.write_str("range"),
+            Self::LfoWaveform => f.write_str("lfo-waveform"),
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "range" => Ok(Self::Range),
+            "lfo-waveform" => Ok(Self::LfoWaveform),
+            _ => s.parse().map(Self::Knob).map_err(|_| {
+                format!(
+                    "unknown target `{s}`, expected `mix`, `frequency`, `rate`, `amount`, `drive`, `range` or `lfo-waveform`"
+                )
+            }),
+        }
+    }
+}
+
+/// turns MIDI messages into knob and switch positions
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MidiControl {
+    channel: Option<u8>,
+    mappings: Vec<(u8, Target)>,
+    key_tracking: bool,
+    /// the keys being held, the last one played sounding
+    held: Vec<u8>,
+    learning: VecDeque<Target>,
+}
+
+impl MidiControl {
+    /// only listens to `channel`, 0 to 15, or to every channel with `None`
+    pub fn set_channel(&mut self, channel: Option<u8>) {
+        self.channel = channel;
+    }
+
+    /// `controller` turns `target`, replacing whatever it turned before
+    pub fn map(&mut self, controller: u8, target: Target) {
+        self.mappings.retain(|(c, _)| *c != controller);
+        self.mappings.push((controller, target));
+    }
+
+    /// the controllers and what they turn
+    pub fn mappings(&self) -> &[(u8, Target)] {
+        &self.mappings
+    }
+
+    /// whether playing a key tunes the carrier to it, clamped to the range
+    pub fn set_key_tracking(&mut self, key_tracking: bool) {
+        self.key_tracking = key_tracking;
+    }
+
+    /// maps the next controllers that are moved to `targets` in order, skipping controllers
+    /// that are already mapped so a controller still being moved isn't learned twice
+    pub fn learn(&mut self, targets: impl IntoIterator<Item = Target>) {
+        self.learning.extend(targets);
+    }
+
+    /// the target the next controller that's moved is mapped to
+    pub fn learning(&self) -> Option<Target> {
+        self.learning.front().copied()
+    }
+
+    /// turns the knobs of `params` as `message` says, returns the mapping learned from it
+    pub fn handle(&mut self, message: Message, params: &mut RingModParams) -> Option<(u8, Target)> {
+        if self.channel.is_some() && message.channel() != self.channel {
+            return None;
+        }
+
+        match message {
+            Message::ControlChange {
+                controller, value, ..
+            } => {
+                let mapped = self.mappings.iter().any(|(c, _)| *c == controller);
+
+                if !mapped {
+                    if let Some(target) = self.learning.pop_front() {
+                        self.map(controller, target);
+                        target.apply(value, params);
+
+                        return Some((controller, target));
+                    }
+                }
+
+                for (_, target) in self.mappings.iter().filter(|(c, _)| *c == controller) {
+                    target.apply(value, params);
+                }
+            }
+            Message::NoteOn { key, .. } if self.key_tracking => {
+                self.held.retain(|k| *k != key);
+                self.held.push(key);
+                params.frequency = params.range.clamp(key_to_frequency(key));
+            }
+            Message::NoteOff { key, .. } if self.key_tracking => {
+                let sounding = self.held.last() == Some(&key);
+                self.held.retain(|k| *k != key);
+
+                // back to the key played before, the carrier keeps running after the last one
+                if let (true, Some(&key)) = (sounding, self.held.last()) {
+                    params.frequency = params.range.clamp(key_to_frequency(key));
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
+
+/// follows the tempo and song position of an incoming MIDI clock
+pub struct MidiClock {
+    sample_rate: u32,
+    /// the frames the last beat's worth of ticks arrived at
+    ticks: VecDeque<u64>,
+    /// beats from the start of the song to the next tick, counted while it's playing
+    song_position: f64,
+    playing: bool,
+}
+
+impl MidiClock {
+    /// a clock for a ring modulator running at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            ticks: VecDeque::with_capacity(TICKS_PER_BEAT + 1),
+            song_position: 0.0,
+            playing: false,
+        }
+    }
+
+    /// follows `message`, which arrived `frame` frames after the start, setting the tempo and
+    /// song position of `modulator`
+    ///
+    /// the tempo is measured over a beat of ticks, and nudged while the song is playing to pull
+    /// the LFO's song position back to the clock's over the next beat
+    pub fn handle(&mut self, message: Message, frame: u64, modulator: &mut RingModulator) {
+        match message {
+            Message::Clock => {
+                let song_position = self.song_position;
+
+                if self.playing {
+                    self.song_position += 1.0 / TICKS_PER_BEAT as f64;
+                }
+
+                if self.ticks.len() > TICKS_PER_BEAT {
+                    self.ticks.pop_front();
+                }
+                self.ticks.push_back(frame);
+
+                let first = self.ticks[0];
+
+                if self.ticks.len() <= TICKS_PER_BEAT || frame == first {
+                    return;
+                }
+
+                let tempo = 60.0 * f64::from(self.sample_rate) / (frame - first) as f64;
+
+                if self.playing {
+                    let drift = song_position - modulator.song_position();
+                    modulator.set_tempo(tempo * (1.0 + drift.clamp(-0.25, 0.25)));
+                } else {
+                    modulator.set_tempo(tempo);
+                }
+            }
+            Message::Start => {
+                self.playing = true;
+                self.song_position = 0.0;
+                modulator.set_song_position(0.0);
+            }
+            Message::Continue => self.playing = true,
+            Message::Stop => self.playing = false,
+            Message::SongPosition { sixteenths } => {
+                self.song_position = f64::from(sixteenths) / 4.0;
+                modulator.set_song_position(self.song_position);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// the messages of a Standard MIDI File at the time they're at in seconds, in order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sequence {
+    events: Vec<(f64, Message)>,
+}
+
+impl Sequence {
+    /// `events` at their time in seconds, which don't need to be sorted
+    pub fn new(mut events: Vec<(f64, Message)>) -> Self {
+        events.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { events }
+    }
+
+    /// reads the messages of every track of a Standard MIDI File, following its tempo changes
+    pub fn from_smf(bytes: &[u8]) -> Result<Self, String> {
+        let smf = Smf::parse(bytes).map_err(|e| e.to_string())?;
+
+        // every event at its tick, tracks play at the same time
+        let mut events = smf
+            .tracks
+            .iter()
+            .flat_map(|track| {
+                track.iter().scan(0u64, |tick, event| {
+                    *tick += u64::from(event.delta.as_int());
+                    Some((*tick, event.kind))
+                })
+            })
+            .collect::<Vec<_>>();
+        events.sort_by_key(|(tick, _)| *tick);
+
+        let mut seconds_per_tick = match smf.header.timing {
+            Timing::Metrical(ticks_per_beat) => {
+                DEFAULT_TEMPO / 1e6 / f64::from(ticks_per_beat.as_int())
+            }
+            Timing::Timecode(fps, ticks_per_frame) => {
+                1.0 / (f64::from(fps.as_f32()) * f64::from(ticks_per_frame))
+            }
+        };
+
+        let mut time = 0.0;
+        let mut last_tick = 0;
+        let mut messages = vec![];
+
+        for (tick, kind) in events {
+            time += (tick - last_tick) as f64 * seconds_per_tick;
+            last_tick = tick;
+
+            match kind {
+                TrackEventKind::Midi { channel, message } => {
+                    if let Some(message) = Message::from_midi(channel.as_int(), message) {
+                        messages.push((time, message));
+                    }
+                }
+                TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                    if let Timing::Metrical(ticks_per_beat) = smf.header.timing {
+                        seconds_per_tick =
+                            f64::from(tempo.as_int()) / 1e6 / f64::from(ticks_per_beat.as_int());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self::new(messages))
+    }
+
+    /// reads a `.mid` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        Self::from_smf(&fs::read(path).map_err(|e| e.to_string())?)
+    }
+
+    /// the messages at their time in seconds, in order
+    pub fn events(&self) -> &[(f64, Message)] {
+        &self.events
+    }
+}
+
+/// a source of MIDI messages read once per block in real-time mode
+pub trait MidiInput {
+    /// appends the messages that arrived since the last poll to `messages`, never blocks
+    fn poll(&mut self, messages: &mut Vec<Message>) -> Result<(), String>;
+}
+
+/// opens the raw MIDI input port called `name`, like `hw:1,0,0`
+pub fn open(name: &str) -> Result<Box<dyn MidiInput>, String> {
+    #[cfg(feature = "alsa")]
+    {
+        AlsaMidiInput::open(name).map(|input| Box::new(input) as Box<dyn MidiInput>)
+    }
+
+    #[cfg(not(feature = "alsa"))]
+    Err(format!(
+        "can't open `{name}`, mf-102 was built without ALSA support, rebuild it with `--features alsa`"
+    ))
+}
+
+/// plays a sequence back one block at a time, standing in for a live input
+pub struct SequenceInput {
+    sequence: Sequence,
+    next: usize,
+    /// seconds from the start to the start of the next block
+    time: f64,
+    block: f64,
+}
+
+impl SequenceInput {
+    /// plays `sequence` back in blocks of `buffer_size` frames at `sample_rate`
+    pub fn new(sequence: Sequence, sample_rate: u32, buffer_size: usize) -> Self {
+        Self {
+            sequence,
+            next: 0,
+            time: 0.0,
+            block: buffer_size as f64 / f64::from(sample_rate),
+        }
+    }
+}
+
+impl MidiInput for SequenceInput {
+    fn poll(&mut self, messages: &mut Vec<Message>) -> Result<(), String> {
+        let due = self.sequence.events[self.next..]
+            .iter()
+            .take_while(|(time, _)| *time <= self.time)
+            .map(|(_, message)| *message);
+
+        let len = messages.len();
+        messages.extend(due);
+        self.next += messages.len() - len;
+        self.time += self.block;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alsa")]
+pub use alsa_input::AlsaMidiInput;
+
+#[cfg(feature = "alsa")]
+mod alsa_input {
+    use super::{Message, MidiInput};
+    use alsa::rawmidi::Rawmidi;
+    use alsa::Direction;
+    use midly::stream::MidiStream;
+    use std::io::{ErrorKind, Read};
+
+    /// an ALSA raw MIDI input port, read without blocking
+    pub struct AlsaMidiInput {
+        rawmidi: Rawmidi,
+        /// reassembles the messages split across reads and running status
+        stream: MidiStream,
+    }
+
+    impl AlsaMidiInput {
+        /// opens the raw MIDI input port called `name`, like `hw:1,0,0`
+        pub fn open(name: &str) -> Result<Self, String> {
+            let rawmidi = Rawmidi::new(name, Direction::Capture, true)
+                .map_err(|e| format!("couldn't open MIDI port `{name}`: {e}"))?;
+
+            Ok(Self {
+                rawmidi,
+                stream: MidiStream::new(),
+            })
+        }
+    }
+
+    impl MidiInput for AlsaMidiInput {
+        fn poll(&mut self, messages: &mut Vec<Message>) -> Result<(), String> {
+            let mut buffer = [0; 256];
+
+            loop {
+                match self.rawmidi.io().read(&mut buffer) {
+                    Ok(0) => return Ok(()),
+                    Ok(read) => self.stream.feed(&buffer[..read], |event| {
+                        messages.extend(Message::from_live(event));
+                    }),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                    Err(e) => return Err(format!("reading MIDI failed: {e}")),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! the MF-102's analog multiplier
+
+use crate::oversample::Oversampler;
+use crate::params::{Multiplier, RingModParams};
+use std::collections::VecDeque;
+
+/// how hard a nonlinearity of 1 drives the multiplier's inputs
+const MAX_SATURATION: f32 = 3.0;
+
+/// the highest oversampling factor of the multiplier
+pub const MAX_OVERSAMPLING: usize = 8;
+
+/// multiplies the input with the carrier, perfectly or with the imperfections of the hardware's
+/// transconductance multiplier
+pub struct MultiplierModel {
+    analog: bool,
+    carrier_bleed: f32,
+    signal_feedthrough: f32,
+    /// gain into the saturation of both inputs, 0 leaves them linear
+    saturation: f32,
+    /// brings a full scale input back to full scale after the saturation
+    makeup: f32,
+}
+
+impl MultiplierModel {
+    /// the multiplier `params` select
+    pub fn new(params: &RingModParams) -> Self {
+        let saturation = params.nonlinearity.clamp(0.0, 1.0) * MAX_SATURATION;
+
+        Self {
+            analog: params.multiplier == Multiplier::Analog,
+            carrier_bleed: params.carrier_bleed.clamp(0.0, 10.0) / 100.0,
+            signal_feedthrough: params.signal_feedthrough.clamp(0.0, 10.0) / 100.0,
+            saturation,
+            makeup: if saturation > 0.0 {
+                1.0 / saturation.tanh()
+            } else {
+                1.0
+            },
+        }
+    }
+
+    /// the wet signal of `sample` ring modulated by `carrier`
+    pub fn multiply(&self, sample: f32, carrier: f32) -> f32 {
+        if !self.analog {
+            return sample * carrier;
+        }
+
+        let product = self.saturate(sample) * self.saturate(carrier);
+
+        product + self.carrier_bleed * carrier + self.signal_feedthrough * sample
+    }
+
+    /// the differential pairs' tanh curve, scaled so full scale stays full scale
+    fn saturate(&self, x: f32) -> f32 {
+        if self.saturation > 0.0 {
+            (x * self.saturation).tanh() * self.makeup
+        } else {
+            x
+        }
+    }
+}
+
+/// a channel's multiplier running at a multiple of the sample rate, so the sum sidebands above
+/// nyquist and the saturation's harmonics are filtered out instead of folding back
+pub struct Oversampled {
+    factor: usize,
+    input: Oversampler,
+    carrier: Oversampler,
+    wet: Oversampler,
+    /// the dry signal and the carrier, delayed to line up with the wet signal
+    delay: VecDeque<(f32, f32)>,
+}
+
+impl Oversampled {
+    /// `factor` being 2, 4 or 8
+    pub fn new(factor: usize) -> Self {
+        debug_assert!((2..=MAX_OVERSAMPLING).contains(&factor));
+
+        let input = Oversampler::high_quality(factor);
+        let latency = input.latency() as usize;
+
+        Self {
+            factor,
+            input,
+            carrier: Oversampler::high_quality(factor),
+            wet: Oversampler::high_quality(factor),
+            delay: VecDeque::from(vec![(0.0, 0.0); latency]),
+        }
+    }
+
+    /// delay added by the round trip in samples, always a whole number
+    pub fn latency(&self) -> f32 {
+        self.input.latency()
+    }
+
+    /// `sample` ring modulated by `carrier` with `multiplier`, returning the dry sample, the carrier
+    /// and the wet sample, all delayed by the latency
+    pub fn multiply(
+        &mut self,
+        multiplier: &MultiplierModel,
+        sample: f32,
+        carrier: f32,
+    ) -> (f32, f32, f32) {
+        let mut samples = [0.0; MAX_OVERSAMPLING];
+        let mut carriers = [0.0; MAX_OVERSAMPLING];
+        let samples = &mut samples[..self.factor];
+        let carriers = &mut carriers[..self.factor];
+
+        self.input.upsample(sample, samples);
+        self.carrier.upsample(carrier, carriers);
+
+        for (sample, carrier) in samples.iter_mut().zip(carriers.iter()) {
+            *sample = multiplier.multiply(*sample, *carrier);
+        }
+
+        let wet = self.wet.downsample(samples);
+
+        self.delay.push_back((sample, carrier));
+        let (sample, carrier) = self.delay.pop_front().unwrap_or((sample, carrier));
+
+        (sample, carrier, wet)
+    }
+}
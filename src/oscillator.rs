@@ -0,0 +1,74 @@
+//! the carrier's waveforms, band-limited with polynomial corrections at their corners and edges
+
+use crate::params::CarrierWaveform;
+
+/// the carrier's value `cycle` through a cycle, 0 to 1, advancing by `increment` of a cycle every
+/// frame, the sine needing no correction
+///
+/// `pulse_width` is the percentage of a cycle the pulse is high, every waveform is in phase with
+/// the sine, crossing zero upwards at the start of a cycle
+pub fn band_limited(
+    waveform: CarrierWaveform,
+    cycle: f32,
+    increment: f32,
+    pulse_width: f32,
+) -> f32 {
+    let t = cycle.rem_euclid(1.0);
+    // the LFO can sweep the carrier through 0 Hz and back, the corrections don't mind the direction
+    let dt = increment.abs().min(0.5);
+    let wrap = |t: f32| t.rem_euclid(1.0);
+
+    match waveform {
+        CarrierWaveform::Sine => (2.0 * std::f32::consts::PI * t).sin(),
+        CarrierWaveform::Triangle => {
+            // peaks a quarter of a cycle in, its slope turning by 8 per cycle at either corner
+            let naive = 4.0 * (wrap(t + 0.75) - 0.5).abs() - 1.0;
+            naive + 8.0 * dt * (blamp(wrap(t + 0.25), dt) - blamp(wrap(t + 0.75), dt))
+        }
+        CarrierWaveform::Square => {
+            let naive = if t < 0.5 { 1.0 } else { -1.0 };
+            naive + blep(t, dt) - blep(wrap(t + 0.5), dt)
+        }
+        CarrierWaveform::Saw => {
+            let t = wrap(t + 0.5);
+            2.0 * t - 1.0 - blep(t, dt)
+        }
+        CarrierWaveform::Pulse => {
+            let width = pulse_width.clamp(5.0, 95.0) / 100.0;
+            let naive = if t < width { 1.0 } else { -1.0 };
+            let offset = 2.0 * width - 1.0;
+            let pulse = naive + blep(t, dt) - blep(wrap(t + 1.0 - width), dt);
+            // centred on 0, a DC offset would let the input through like signal feedthrough,
+            // and scaled back to peak at full scale
+            (pulse - offset) / (1.0 + offset.abs())
+        }
+    }
+}
+
+/// the correction rounding off a rising step of 2 at the start of a cycle, `t` being the position
+/// in the cycle and `dt` the increment per frame
+fn blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let t = t / dt;
+        2.0 * t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// the correction rounding off a corner at the start of a cycle where the slope rises by 1 per frame,
+/// the integral of [`blep`]
+fn blamp(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let t = t / dt - 1.0;
+        -t * t * t / 3.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt + 1.0;
+        t * t * t / 3.0
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,205 @@
+//! polyphase FIR up/down-sampling used around the nonlinear stages
+
+use std::f32::consts::PI;
+
+/// number of FIR taps per polyphase branch
+const TAPS_PER_PHASE: usize = 16;
+
+/// number of FIR taps per polyphase branch of the steeper filters
+const HIGH_QUALITY_TAPS_PER_PHASE: usize = 64;
+
+/// upsamples a signal by `factor`, lets the caller process it at the higher rate and then
+/// band-limits and decimates it back down
+pub struct Oversampler {
+    factor: usize,
+    /// windowed sinc lowpass at the original nyquist, normalized to unity gain at DC
+    kernel: Vec<f32>,
+    taps_per_phase: usize,
+    /// delay of a round trip in samples at the original rate
+    latency: f32,
+    /// most recent input samples, used by the upsampling polyphase branches
+    up_history: Vec<f32>,
+    up_pos: usize,
+    /// most recent oversampled samples, used by the decimation filter
+    down_history: Vec<f32>,
+    down_pos: usize,
+}
+
+impl Oversampler {
+    pub fn new(factor: usize) -> Self {
+        assert!(factor > 0, "oversampling factor must be at least 1");
+
+        let len = TAPS_PER_PHASE * factor;
+        // leave a little headroom below the original nyquist for the transition band
+        let cutoff = 0.45 / factor as f32;
+        let center = (len - 1) as f32 / 2.0;
+
+        let mut kernel = (0..len)
+            .map(|i| {
+                let t = i as f32 - center;
+                let sinc = if t == 0.0 {
+                    2.0 * cutoff
+                } else {
+                    (2.0 * PI * cutoff * t).sin() / (PI * t)
+                };
+                // blackman window
+                let w = 2.0 * PI * i as f32 / (len - 1) as f32;
+                sinc * (0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos())
+            })
+            .collect::<Vec<f32>>();
+
+        let sum = kernel.iter().sum::<f32>();
+        kernel.iter_mut().for_each(|k| *k /= sum);
+
+        Self {
+            factor,
+            kernel,
+            taps_per_phase: TAPS_PER_PHASE,
+            // as with `high_quality`, the decimation keeps the last oversampled sample of a frame
+            latency: (len - factor) as f32 / factor as f32,
+            up_history: vec![0.0; TAPS_PER_PHASE],
+            up_pos: 0,
+            down_history: vec![0.0; len],
+            down_pos: 0,
+        }
+    }
+
+    /// like [`new`](Self::new) with much longer and steeper filters, their stopband below -90dB
+    /// from just above the original nyquist, and a round trip delaying by a whole number of samples
+    pub fn high_quality(factor: usize) -> Self {
+        assert!(factor > 0, "oversampling factor must be at least 1");
+
+        let len = HIGH_QUALITY_TAPS_PER_PHASE * factor;
+        let cutoff = 0.45 / factor as f32;
+        let center = (len - 1) as f32 / 2.0;
+
+        let mut kernel = (0..len)
+            .map(|i| {
+                let t = i as f32 - center;
+                let sinc = if t == 0.0 {
+                    2.0 * cutoff
+                } else {
+                    (2.0 * PI * cutoff * t).sin() / (PI * t)
+                };
+                // 4 term blackman-harris window
+                let w = 2.0 * PI * i as f32 / (len - 1) as f32;
+                sinc * (0.35875 - 0.48829 * w.cos() + 0.14128 * (2.0 * w).cos()
+                    - 0.01168 * (3.0 * w).cos())
+            })
+            .collect::<Vec<f32>>();
+
+        let sum = kernel.iter().sum::<f32>();
+        kernel.iter_mut().for_each(|k| *k /= sum);
+
+        Self {
+            factor,
+            kernel,
+            taps_per_phase: HIGH_QUALITY_TAPS_PER_PHASE,
+            // each filter delays by half its length at the higher rate, and the decimation keeps
+            // the last oversampled sample of every frame, which is `factor - 1` of them later
+            latency: (len - factor) as f32 / factor as f32,
+            up_history: vec![0.0; HIGH_QUALITY_TAPS_PER_PHASE],
+            up_pos: 0,
+            down_history: vec![0.0; len],
+            down_pos: 0,
+        }
+    }
+
+    /// writes `factor` oversampled samples for `sample` into `out`
+    pub fn upsample(&mut self, sample: f32, out: &mut [f32]) {
+        debug_assert_eq!(out.len(), self.factor);
+
+        if self.factor == 1 {
+            out[0] = sample;
+            return;
+        }
+
+        let taps = self.taps_per_phase;
+
+        self.up_pos = (self.up_pos + 1) % taps;
+        self.up_history[self.up_pos] = sample;
+
+        for (phase, out) in out.iter_mut().enumerate() {
+            let mut acc = 0.0;
+
+            for tap in 0..taps {
+                let idx = (self.up_pos + taps - tap) % taps;
+                acc += self.kernel[tap * self.factor + phase] * self.up_history[idx];
+            }
+
+            // zero stuffing spreads the energy over `factor` samples
+            *out = acc * self.factor as f32;
+        }
+    }
+
+    /// band-limits `factor` oversampled samples and returns the decimated one
+    pub fn downsample(&mut self, samples: &[f32]) -> f32 {
+        debug_assert_eq!(samples.len(), self.factor);
+
+        if self.factor == 1 {
+            return samples[0];
+        }
+
+        let len = self.down_history.len();
+
+        for &sample in samples {
+            self.down_pos = (self.down_pos + 1) % len;
+            self.down_history[self.down_pos] = sample;
+        }
+
+        self.kernel
+            .iter()
+            .enumerate()
+            .map(|(tap, k)| k * self.down_history[(self.down_pos + len - tap) % len])
+            .sum()
+    }
+
+    /// delay added by a full up and down sampling round trip, in samples at the original rate
+    pub fn latency(&self) -> f32 {
+        if self.factor == 1 {
+            0.0
+        } else {
+            self.latency
+        }
+    }
+
+    /// delay added by upsampling, in samples at the original rate
+    pub fn delay(&self) -> usize {
+        if self.factor == 1 {
+            0
+        } else {
+            self.taps_per_phase / 2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// where an impulse comes out of a round trip, the loudest sample
+    fn peak(mut oversampler: Oversampler, factor: usize) -> usize {
+        let mut up = vec![0.0; factor];
+
+        (0..200)
+            .map(|i| {
+                oversampler.upsample(if i == 0 { 1.0 } else { 0.0 }, &mut up);
+                oversampler.downsample(&up).abs()
+            })
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn latency_is_where_an_impulse_comes_out() {
+        for factor in [2, 4, 8] {
+            for oversampler in [Oversampler::new, Oversampler::high_quality] {
+                let latency = oversampler(factor).latency();
+
+                assert_eq!(peak(oversampler(factor), factor) as f32, latency);
+            }
+        }
+    }
+}
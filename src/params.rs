@@ -0,0 +1,554 @@
+//! the knobs and switches of the MF-102
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// the shape of the LFO modulating the carrier's frequency
+///
+/// with [`LfoModulation::Octaves`] every shape sweeps the carrier between the frequency and
+/// `amount` above it, with [`LfoModulation::Linear`] every shape but the square swings the carrier
+/// between `amount` below and above the frequency, the square jumps between the frequency and
+/// `amount` above it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Waveform {
+    /// Sinusoidal LFO wave form will smoothly oscillate between 0-3 octaves above PARAMS.frequency
+    #[serde(rename = "sine")]
+    Sinusoidal,
+    /// Square LFO wave form instantaneously jumps between an unaffected carrier signal and 3 octaves above PARAMS.frequency
+    Square,
+    /// rises and falls in straight lines
+    Triangle,
+    /// rises in a straight line and drops back at the end of every cycle
+    RampUp,
+    /// falls in a straight line and jumps back up at the end of every cycle
+    RampDown,
+    /// holds a new random value for every cycle
+    SampleAndHold,
+    /// glides from one random value to the next over every cycle
+    SmoothRandom,
+}
+
+impl Waveform {
+    /// every waveform, in the order a controller sweeps through them
+    pub const ALL: [Self; 7] = [
+        Self::Sinusoidal,
+        Self::Square,
+        Self::Triangle,
+        Self::RampUp,
+        Self::RampDown,
+        Self::SampleAndHold,
+        Self::SmoothRandom,
+    ];
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sinusoidal => "sine",
+            Self::Square => "square",
+            Self::Triangle => "triangle",
+            Self::RampUp => "ramp-up",
+            Self::RampDown => "ramp-down",
+            Self::SampleAndHold => "sample-and-hold",
+            Self::SmoothRandom => "smooth-random",
+        })
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|waveform| waveform.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown waveform `{s}`, expected `sine`, `square`, `triangle`, `ramp-up`, `ramp-down`, `sample-and-hold` or `smooth-random`"
+                )
+            })
+    }
+}
+
+/// the shape of the carrier, every shape but the sine band-limited so it doesn't alias
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CarrierWaveform {
+    /// the hardware's carrier
+    #[default]
+    Sine,
+    /// rises and falls in straight lines, a little brighter than the sine
+    Triangle,
+    /// a hollow, buzzy square wave
+    Square,
+    /// a bright sawtooth with every harmonic
+    Saw,
+    /// a square narrowed to `carrier_pulse_width`, thinner the narrower it is
+    Pulse,
+}
+
+impl CarrierWaveform {
+    /// every carrier waveform, from the softest to the harshest
+    pub const ALL: [Self; 5] = [
+        Self::Sine,
+        Self::Triangle,
+        Self::Square,
+        Self::Saw,
+        Self::Pulse,
+    ];
+}
+
+impl fmt::Display for CarrierWaveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sine => "sine",
+            Self::Triangle => "triangle",
+            Self::Square => "square",
+            Self::Saw => "saw",
+            Self::Pulse => "pulse",
+        })
+    }
+}
+
+impl FromStr for CarrierWaveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|waveform| waveform.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown carrier waveform `{s}`, expected `sine`, `triangle`, `square`, `saw` or `pulse`"
+                )
+            })
+    }
+}
+
+/// how the LFO bends the carrier's frequency
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LfoModulation {
+    /// up to `amount` of 3 octaves above the frequency, every octave taking as much of the LFO's
+    /// swing
+    #[default]
+    Octaves,
+    /// adds up to `amount` of 3 times the frequency, the bipolar waveforms also sweeping below the
+    /// frequency and through 0 Hz, like renders before octaves
+    Linear,
+}
+
+impl FromStr for LfoModulation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "octaves" => Ok(Self::Octaves),
+            "linear" => Ok(Self::Linear),
+            _ => Err(format!(
+                "unknown LFO modulation `{s}`, expected `octaves` or `linear`"
+            )),
+        }
+    }
+}
+
+/// how a note division's length is changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feel {
+    /// as written
+    Straight,
+    /// half as long again
+    Dotted,
+    /// two thirds as long, three in the time of two
+    Triplet,
+}
+
+/// a note length like `1/4`, `1/8t` or `1/16d`, `1/1` being a bar of 4/4
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoteDivision {
+    /// how many notes
+    pub count: u16,
+    /// of which length, 4 being a quarter note
+    pub note: u16,
+    /// dotted or triplet
+    pub feel: Feel,
+}
+
+impl NoteDivision {
+    /// how long the division lasts in beats, quarter notes
+    pub fn beats(self) -> f64 {
+        let beats = 4.0 * f64::from(self.count) / f64::from(self.note);
+
+        match self.feel {
+            Feel::Straight => beats,
+            Feel::Dotted => beats * 1.5,
+            Feel::Triplet => beats * 2.0 / 3.0,
+        }
+    }
+}
+
+impl fmt::Display for NoteDivision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let feel = match self.feel {
+            Feel::Straight => "",
+            Feel::Dotted => "d",
+            Feel::Triplet => "t",
+        };
+
+        write!(f, "{}/{}{feel}", self.count, self.note)
+    }
+}
+
+impl FromStr for NoteDivision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "`{s}` isn't a note division, expected one like `1/4`, `1/8t`, `1/16d` or `2/1`"
+            )
+        };
+
+        let lower = s.trim().to_lowercase();
+
+        // `2 bars` is `2/1`
+        if let Some(bars) = lower
+            .strip_suffix("bars")
+            .or_else(|| lower.strip_suffix("bar"))
+        {
+            return match bars.trim().parse() {
+                Ok(count @ 1..) => Ok(Self {
+                    count,
+                    note: 1,
+                    feel: Feel::Straight,
+                }),
+                _ => Err(err()),
+            };
+        }
+
+        let (division, feel) = if let Some(division) = lower.strip_suffix('t') {
+            (division, Feel::Triplet)
+        } else if let Some(division) = lower.strip_suffix(['d', '.']) {
+            (division, Feel::Dotted)
+        } else {
+            (lower.as_str(), Feel::Straight)
+        };
+
+        let (count, note) = division.split_once('/').ok_or_else(err)?;
+
+        match (count.parse(), note.parse()) {
+            (Ok(count @ 1..), Ok(note @ 1..)) => Ok(Self { count, note, feel }),
+            _ => Err(err()),
+        }
+    }
+}
+
+/// what sets the speed of the LFO
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum LfoSync {
+    /// runs at `rate`
+    #[default]
+    Free,
+    /// a cycle lasts the note division at the tempo, in phase with the song position
+    Tempo(NoteDivision),
+}
+
+impl fmt::Display for LfoSync {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Free => f.write_str("free"),
+            Self::Tempo(division) => division.fmt(f),
+        }
+    }
+}
+
+impl FromStr for LfoSync {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "free" => Ok(Self::Free),
+            _ => s
+                .parse()
+                .map(Self::Tempo)
+                .map_err(|e: String| format!("{e}, or `free`")),
+        }
+    }
+}
+
+impl From<LfoSync> for String {
+    fn from(sync: LfoSync) -> Self {
+        sync.to_string()
+    }
+}
+
+impl TryFrom<String> for LfoSync {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// the LO/HI switch selecting the carrier's frequency range
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrequencyRange {
+    /// 0.6Hz to 80Hz
+    Lo,
+    /// 30Hz to 4kHz
+    Hi,
+}
+
+impl FrequencyRange {
+    /// the lowest and highest carrier frequency of the range in Hz
+    pub fn bounds(self) -> (f32, f32) {
+        match self {
+            Self::Lo => (0.6, 80.0),
+            Self::Hi => (30.0, 4000.0),
+        }
+    }
+
+    /// maps a 0 to 10 frequency knob position onto the range with the dial's exponential taper
+    pub fn knob_to_frequency(self, knob: f32) -> f32 {
+        let (min, max) = self.bounds();
+        min * (max / min).powf(knob.clamp(0.0, 10.0) / 10.0)
+    }
+
+    /// the 0 to 10 frequency knob position of `frequency` in Hz, the inverse of
+    /// [`knob_to_frequency`](Self::knob_to_frequency)
+    pub fn frequency_to_knob(self, frequency: f32) -> f32 {
+        let (min, max) = self.bounds();
+        10.0 * (self.clamp(frequency) / min).ln() / (max / min).ln()
+    }
+
+    /// whether `frequency` in Hz is within the range
+    pub fn contains(self, frequency: f32) -> bool {
+        let (min, max) = self.bounds();
+        (min..=max).contains(&frequency)
+    }
+
+    /// `frequency` in Hz clamped to the range
+    pub fn clamp(self, frequency: f32) -> f32 {
+        let (min, max) = self.bounds();
+        frequency.clamp(min, max)
+    }
+}
+
+impl FromStr for FrequencyRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lo" => Ok(Self::Lo),
+            "hi" => Ok(Self::Hi),
+            _ => Err(format!("unknown range `{s}`, expected `lo` or `hi`")),
+        }
+    }
+}
+
+/// how the input and the carrier are multiplied
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Multiplier {
+    /// a mathematically perfect multiplier
+    #[default]
+    Ideal,
+    /// the hardware's transconductance multiplier, leaking some of the carrier and the input
+    /// through and slightly compressing both
+    Analog,
+}
+
+impl FromStr for Multiplier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ideal" => Ok(Self::Ideal),
+            "analog" => Ok(Self::Analog),
+            _ => Err(format!(
+                "unknown multiplier `{s}`, expected `ideal` or `analog`"
+            )),
+        }
+    }
+}
+
+/// every setting of the ring modulator, `Default` being the knobs this recreation started with
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RingModParams {
+    /// Input section
+    /// 0 to 10, gain of the input stage driving into its soft clipping, 0 bypasses it
+    pub drive: f32,
+
+    /// LFO section
+    /// 0 to 10, this is normalized and controls a percentage of a 3 octave jump
+    pub amount: f32,
+    /// the waveform for the carrier LFO modulation
+    pub lfo_waveform: Waveform,
+    /// 5 to 95 percent of a cycle the square LFO is high
+    pub pulse_width: f32,
+    /// 0 to 100 ms the square LFO takes to swing between its levels, 0 jumping instantly
+    pub lfo_slew: f32,
+    /// whether the LFO sweeps the carrier in octaves or in Hz
+    pub lfo_modulation: LfoModulation,
+    /// 0.1Hz to 25Hz the rate of the LFO modulation on the carrier signal
+    pub rate: f32,
+    /// locks the LFO to the tempo instead of `rate`
+    pub sync: LfoSync,
+
+    /// Modulator section
+    /// 0 to 100, mix with the original sampled signal
+    pub mix: u8,
+    /// 0.6Hz to 80Hz (LO setting), 30Hz to 4kHz (HI setting) for the carrier signal
+    /// values outside of `range` are clamped to it
+    pub frequency: f32,
+    /// the LO/HI switch for `frequency`
+    pub range: FrequencyRange,
+    /// 0 to 180 degrees, each channel's carrier is offset by this much from the previous channel's
+    pub channel_phase_offset: f32,
+    /// the shape of the carrier
+    pub carrier_waveform: CarrierWaveform,
+    /// 5 to 95 percent of a cycle the pulse carrier is high
+    pub carrier_pulse_width: f32,
+
+    /// Multiplier
+    /// `analog` models the imperfections below, `ideal` ignores them
+    pub multiplier: Multiplier,
+    /// 0 to 10 percent of the carrier leaking into the wet signal
+    pub carrier_bleed: f32,
+    /// 0 to 10 percent of the input leaking into the wet signal
+    pub signal_feedthrough: f32,
+    /// 0 to 1, how hard the multiplier compresses the input and the carrier
+    pub nonlinearity: f32,
+    /// 1, 2, 4 or 8 times the sample rate the multiplier runs at, so sidebands above nyquist don't
+    /// fold back, at the cost of some latency, fixed once a ring modulator is created
+    pub oversampling: u32,
+
+    /// Vintage
+    /// whether the oscillators drift and follow the tolerances of `unit`
+    pub vintage: bool,
+    /// the unit whose component tolerances are modeled, each one its own consistent set
+    pub unit: u32,
+    /// 0 to 50 cents the carrier and a free running LFO slowly drift either way
+    pub drift: f32,
+}
+
+impl RingModParams {
+    /// the settings used when nothing else is given, also the `init` factory preset
+    pub const DEFAULT: Self = Self {
+        drive: 0.0,
+        amount: 6.7,
+        lfo_waveform: Waveform::Square,
+        pulse_width: 50.0,
+        lfo_slew: 0.0,
+        lfo_modulation: LfoModulation::Octaves,
+        rate: 0.18,
+        sync: LfoSync::Free,
+        mix: 71,
+        frequency: 156.0,
+        range: FrequencyRange::Hi,
+        channel_phase_offset: 0.0,
+        carrier_waveform: CarrierWaveform::Sine,
+        carrier_pulse_width: 25.0,
+        multiplier: Multiplier::Ideal,
+        // ballpark figures for the MF-102's multiplier, only heard with `Multiplier::Analog`
+        carrier_bleed: 0.8,
+        signal_feedthrough: 0.5,
+        nonlinearity: 0.25,
+        oversampling: 1,
+        vintage: false,
+        unit: 0,
+        drift: 8.0,
+    };
+
+    /// the range of `drive`
+    pub const DRIVE: RangeInclusive<f32> = 0.0..=10.0;
+    /// the range of `amount`
+    pub const AMOUNT: RangeInclusive<f32> = 0.0..=10.0;
+    /// the range of `pulse_width` and `carrier_pulse_width`
+    pub const PULSE_WIDTH: RangeInclusive<f32> = 5.0..=95.0;
+    /// the range of `lfo_slew`
+    pub const LFO_SLEW: RangeInclusive<f32> = 0.0..=100.0;
+    /// the range of `rate`
+    pub const RATE: RangeInclusive<f32> = 0.1..=25.0;
+    /// the range of `mix`
+    pub const MIX: RangeInclusive<u8> = 0..=100;
+    /// the range of `channel_phase_offset`
+    pub const CHANNEL_PHASE_OFFSET: RangeInclusive<f32> = 0.0..=180.0;
+    /// the range of `carrier_bleed` and `signal_feedthrough`
+    pub const LEAK: RangeInclusive<f32> = 0.0..=10.0;
+    /// the range of `nonlinearity`
+    pub const NONLINEARITY: RangeInclusive<f32> = 0.0..=1.0;
+    /// the factors `oversampling` takes
+    pub const OVERSAMPLING: [u32; 4] = [1, 2, 4, 8];
+    /// the range of `drift`
+    pub const DRIFT: RangeInclusive<f32> = 0.0..=50.0;
+
+    /// checks that every knob is within its range, naming the first one that isn't, the
+    /// frequency only has to be a number as it's clamped to `range`
+    pub fn validate(&self) -> Result<(), String> {
+        let knob = |name: &str, value: f32, range: RangeInclusive<f32>| {
+            Self::in_range(value, &range).map_err(|e| format!("`{name}`: {e}"))
+        };
+
+        knob("drive", self.drive, Self::DRIVE)?;
+        knob("amount", self.amount, Self::AMOUNT)?;
+        knob("pulse_width", self.pulse_width, Self::PULSE_WIDTH)?;
+        knob("lfo_slew", self.lfo_slew, Self::LFO_SLEW)?;
+        knob("rate", self.rate, Self::RATE)?;
+        Self::in_range(self.mix, &Self::MIX).map_err(|e| format!("`mix`: {e}"))?;
+        knob(
+            "channel_phase_offset",
+            self.channel_phase_offset,
+            Self::CHANNEL_PHASE_OFFSET,
+        )?;
+        knob(
+            "carrier_pulse_width",
+            self.carrier_pulse_width,
+            Self::PULSE_WIDTH,
+        )?;
+        knob("carrier_bleed", self.carrier_bleed, Self::LEAK)?;
+        knob("signal_feedthrough", self.signal_feedthrough, Self::LEAK)?;
+        knob("nonlinearity", self.nonlinearity, Self::NONLINEARITY)?;
+        knob("drift", self.drift, Self::DRIFT)?;
+
+        if !self.frequency.is_finite() {
+            return Err(format!("`frequency`: {} isn't a frequency", self.frequency));
+        }
+
+        if !Self::OVERSAMPLING.contains(&self.oversampling) {
+            return Err(format!(
+                "`oversampling`: {} isn't 1, 2, 4 or 8",
+                self.oversampling
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// `value` if it's within `range`, one of the ranges above
+    pub fn in_range<T: PartialOrd + fmt::Display>(
+        value: T,
+        range: &RangeInclusive<T>,
+    ) -> Result<T, String> {
+        if range.contains(&value) {
+            Ok(value)
+        } else {
+            Err(format!(
+                "{value} is not in {}..={}",
+                range.start(),
+                range.end()
+            ))
+        }
+    }
+}
+
+impl Default for RingModParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
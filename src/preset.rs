@@ -0,0 +1,636 @@
+//! versioned preset files, the preset directory and the user config
+
+use crate::factory::{self, FactoryPreset};
+use crate::params::RingModParams;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// the preset schema version written by this version of mf-102
+///
+/// 1. the original knobs: `amount`, `lfo_waveform`, `rate`, `mix` and `frequency`
+/// 2. adds `drive`, `range` and `channel_phase_offset`
+/// 3. adds `inherits`, with `params` only holding what differs from the inherited preset
+/// 4. adds `sync`
+/// 5. adds `multiplier`, `carrier_bleed`, `signal_feedthrough` and `nonlinearity`
+/// 6. adds `vintage`, `unit` and `drift`
+/// 7. adds `pulse_width`
+/// 8. adds `carrier_waveform` and `carrier_pulse_width`
+/// 9. adds `lfo_slew`
+/// 10. adds `lfo_modulation`
+/// 11. adds `oversampling`
+pub const VERSION: u32 = 11;
+
+/// a named set of knob positions as stored on disk
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    /// the schema version the preset was written with
+    pub version: u32,
+    /// the user or factory preset this one is based on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inherits: Option<String>,
+    /// what the preset sounds like
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// the knob positions
+    pub params: RingModParams,
+}
+
+impl Preset {
+    /// a preset of the current version
+    pub fn new(params: RingModParams, description: Option<String>) -> Self {
+        Self {
+            version: VERSION,
+            inherits: None,
+            description,
+            params,
+        }
+    }
+
+    /// reads a preset of any version from TOML, inheriting from factory presets only,
+    /// see [`PresetLibrary`] for user presets
+    pub fn from_toml(toml: &str) -> Result<Self, String> {
+        let preset = toml::from_str(toml).map_err(|e| e.to_string())?;
+        Self::parse(preset, &mut factory_preset)
+    }
+
+    /// reads a preset of any version from JSON, inheriting from factory presets only
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let preset = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        Self::parse(preset, &mut factory_preset)
+    }
+
+    /// the preset as TOML
+    pub fn to_toml(&self) -> String {
+        to_toml(&to_value(self))
+    }
+
+    /// the preset as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("presets are representable in JSON")
+    }
+
+    /// reads a `.toml` or `.json` preset file, inheriting from factory presets only
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        Self::parse(read(path.as_ref())?, &mut factory_preset)
+    }
+
+    /// writes a `.toml` or `.json` preset file with every knob
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        write(path.as_ref(), &to_value(self))
+    }
+
+    /// migrates a preset and fills in the knobs it doesn't set from the preset it inherits,
+    /// which `base` resolves
+    fn parse(
+        preset: Value,
+        base: &mut dyn FnMut(&str) -> Result<Preset, String>,
+    ) -> Result<Self, String> {
+        let mut preset = migrate(preset)?;
+
+        if let Some(inherits) = preset.get("inherits").and_then(Value::as_str) {
+            let mut params = to_map(&base(inherits)?.params);
+
+            if let Some(own) = preset.get("params").and_then(Value::as_object) {
+                params.extend(own.clone());
+            }
+
+            preset["params"] = Value::Object(params);
+        }
+
+        let preset: Self = serde_json::from_value(preset).map_err(|e| e.to_string())?;
+        preset.params.validate()?;
+
+        Ok(preset)
+    }
+}
+
+fn factory_preset(name: &str) -> Result<Preset, String> {
+    factory::find(name)
+        .map(FactoryPreset::preset)
+        .ok_or_else(|| format!("there's no factory preset called `{name}`"))
+}
+
+/// `value` by way of JSON, which writes the knobs' `f32`s in their shortest form instead of
+/// widening them to `f64`
+fn to_value(value: &impl Serialize) -> Value {
+    let json = serde_json::to_string(value).expect("presets are representable in JSON");
+    serde_json::from_str(&json).expect("serde_json writes valid JSON")
+}
+
+fn to_map(params: &RingModParams) -> Map<String, Value> {
+    match to_value(params) {
+        Value::Object(params) => params,
+        _ => unreachable!("parameters serialize to a map"),
+    }
+}
+
+fn to_toml(value: &Value) -> String {
+    let value: toml::Value =
+        serde_json::from_value(value.clone()).expect("presets are representable in TOML");
+    toml::to_string_pretty(&value).expect("presets are representable in TOML")
+}
+
+fn read(path: &Path) -> Result<Value, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    match Format::of(path)? {
+        Format::Toml => toml::from_str(&contents).map_err(|e| e.to_string()),
+        Format::Json => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+    }
+}
+
+fn write(path: &Path, value: &Value) -> Result<(), String> {
+    let contents = match Format::of(path)? {
+        Format::Toml => to_toml(value),
+        Format::Json => serde_json::to_string_pretty(value).expect("JSON values are representable"),
+    };
+
+    fs::write(path, contents).map_err(|e| e.to_string())
+}
+
+enum Format {
+    Toml,
+    Json,
+}
+
+impl Format {
+    fn of(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Ok(Self::Toml),
+            Some("json") => Ok(Self::Json),
+            _ => Err("expected a `.toml` or `.json` file".to_string()),
+        }
+    }
+}
+
+/// brings a preset of any earlier version up to [`VERSION`], presets without a version being
+/// version 1
+fn migrate(mut preset: Value) -> Result<Value, String> {
+    let object = preset.as_object_mut().ok_or("a preset has to be a table")?;
+
+    let mut version = match object.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or("invalid version")?,
+    };
+
+    if version > VERSION {
+        return Err(format!(
+            "the preset is version {version}, this version of mf-102 only reads up to {VERSION}"
+        ));
+    }
+
+    let params = object
+        .entry("params")
+        .or_insert(json!({}))
+        .as_object_mut()
+        .ok_or("`params` has to be a table")?;
+
+    // knobs an old preset didn't have yet get their old values in its own params, so an inherited
+    // preset that's been updated since doesn't change how it sounds
+    while version < VERSION {
+        match version {
+            1 => migrate_v1(params),
+            // inheriting is opt-in, version 2 presets set every knob
+            2 => {}
+            // the LFO always ran free
+            3 => {
+                params.entry("sync").or_insert(json!("free"));
+            }
+            // the multiplier was ideal
+            4 => {
+                params.entry("multiplier").or_insert(json!("ideal"));
+                params.entry("carrier_bleed").or_insert(json!(0.8));
+                params.entry("signal_feedthrough").or_insert(json!(0.5));
+                params.entry("nonlinearity").or_insert(json!(0.25));
+            }
+            // the oscillators were perfectly stable
+            5 => {
+                params.entry("vintage").or_insert(json!(false));
+                params.entry("unit").or_insert(json!(0));
+                params.entry("drift").or_insert(json!(8.0));
+            }
+            // the square LFO was high for half of every cycle
+            6 => {
+                params.entry("pulse_width").or_insert(json!(50.0));
+            }
+            // the carrier was always a sine
+            7 => {
+                params.entry("carrier_waveform").or_insert(json!("sine"));
+                params.entry("carrier_pulse_width").or_insert(json!(25.0));
+            }
+            // the square LFO jumped instantly
+            8 => {
+                params.entry("lfo_slew").or_insert(json!(0.0));
+            }
+            // the LFO swept the carrier linearly
+            9 => {
+                params.entry("lfo_modulation").or_insert(json!("linear"));
+            }
+            // the multiplier ran at the sample rate
+            10 => {
+                params.entry("oversampling").or_insert(json!(1));
+            }
+            _ => unreachable!("every version below the current one has a migration"),
+        }
+
+        version += 1;
+    }
+
+    object.insert("version".to_string(), json!(VERSION));
+
+    Ok(preset)
+}
+
+/// the drive knob didn't exist and the frequency could be anywhere from 0.6Hz to 4kHz
+fn migrate_v1(params: &mut Map<String, Value>) {
+    // the range switch goes where the frequency is, preferring HI where both overlap
+    let range = match params.get("frequency").and_then(Value::as_f64) {
+        Some(frequency) if frequency < 30.0 => "lo",
+        _ => "hi",
+    };
+
+    params.entry("drive").or_insert(json!(0.0));
+    params.entry("range").or_insert(json!(range));
+    params.entry("channel_phase_offset").or_insert(json!(0.0));
+}
+
+/// the `mf-102` directory in the user's config directory
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("mf-102"))
+}
+
+/// the presets saved in a directory, one `<name>.toml` or `<name>.json` file each,
+/// on top of the factory presets
+///
+/// a user preset overrides the factory preset of the same name, and can inherit from a
+/// factory or another user preset
+pub struct PresetLibrary {
+    dir: Option<PathBuf>,
+}
+
+impl PresetLibrary {
+    /// the presets in `dir`, which is created when saving the first preset
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: Some(dir.into()),
+        }
+    }
+
+    /// only the factory presets, saving isn't possible
+    pub fn factory() -> Self {
+        Self { dir: None }
+    }
+
+    /// the directory holding the user presets
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// the names of the user presets, sorted
+    pub fn list(&self) -> Result<Vec<String>, String> {
+        let Some(dir) = &self.dir else {
+            return Ok(vec![]);
+        };
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(format!("couldn't read `{}`: {e}", dir.display())),
+        };
+
+        let mut names = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| Format::of(path).is_ok())
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+            .collect::<Vec<_>>();
+
+        names.sort();
+        names.dedup();
+
+        Ok(names)
+    }
+
+    /// the file of the user preset called `name`, if there is one
+    pub fn find(&self, name: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+
+        ["toml", "json"]
+            .into_iter()
+            .map(|extension| dir.join(format!("{name}.{extension}")))
+            .find(|path| path.is_file())
+    }
+
+    /// loads the user preset called `name`, or the factory preset if there's no user preset
+    pub fn load(&self, name: &str) -> Result<Preset, String> {
+        self.load_inherited(name, &mut vec![])
+    }
+
+    /// loads a `.toml` or `.json` preset file, which can inherit from the library's presets
+    pub fn load_file(&self, path: impl AsRef<Path>) -> Result<Preset, String> {
+        let path = path.as_ref();
+
+        Preset::parse(read(path)?, &mut |base| {
+            self.load_inherited(base, &mut vec![])
+        })
+        .map_err(|e| format!("couldn't load `{}`: {e}", path.display()))
+    }
+
+    /// `chain` holds the user presets inheriting from `name`, a user preset inheriting from
+    /// one of them inherits from the factory preset of that name instead, so a user preset
+    /// can override a factory preset while being based on it
+    fn load_inherited(&self, name: &str, chain: &mut Vec<String>) -> Result<Preset, String> {
+        let inheriting = chain.iter().any(|n| n == name);
+
+        let path = match self.find(name) {
+            Some(path) if !inheriting => path,
+            _ => {
+                return match factory::find(name) {
+                    Some(preset) => Ok(preset.preset()),
+                    None if inheriting => Err(format!("`{name}` inherits from itself")),
+                    None => Err(format!("there's no preset called `{name}`")),
+                };
+            }
+        };
+
+        chain.push(name.to_string());
+
+        Preset::parse(read(&path)?, &mut |base| self.load_inherited(base, chain))
+            .map_err(|e| format!("couldn't load `{}`: {e}", path.display()))
+    }
+
+    /// saves `preset` as `<name>.toml` returning its path, only keeping the knobs that differ
+    /// from the preset it inherits
+    pub fn save(&self, name: &str, preset: &Preset) -> Result<PathBuf, String> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or("there's no directory to save presets in")?;
+
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(format!("`{name}` isn't a valid preset name"));
+        }
+
+        let mut value = to_value(preset);
+
+        if let Some(inherits) = &preset.inherits {
+            let base = self.load_inherited(inherits, &mut vec![name.to_string()])?;
+            let base = to_map(&base.params);
+
+            if let Some(params) = value["params"].as_object_mut() {
+                params.retain(|knob, value| base.get(knob) != Some(value));
+            }
+        }
+
+        fs::create_dir_all(dir).map_err(|e| format!("couldn't create `{}`: {e}", dir.display()))?;
+
+        let path = dir.join(format!("{name}.toml"));
+        write(&path, &value).map_err(|e| format!("couldn't save `{}`: {e}", path.display()))?;
+
+        Ok(path)
+    }
+}
+
+/// the user's `config.toml`, supplying the knob positions that aren't set otherwise
+///
+/// ```toml
+/// version = 11
+/// preset_dir = "/home/me/presets"
+///
+/// [defaults]
+/// mix = 50
+/// drive = 2.0
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UserConfig {
+    /// where presets are kept instead of `presets` in the config directory
+    pub preset_dir: Option<PathBuf>,
+    /// every knob, those missing from the config being [`RingModParams::default`]
+    pub defaults: RingModParams,
+}
+
+#[derive(Deserialize)]
+struct UserConfigFile {
+    version: Option<u32>,
+    preset_dir: Option<PathBuf>,
+    #[serde(default)]
+    defaults: Map<String, Value>,
+}
+
+impl UserConfig {
+    /// `config.toml` in [`config_dir`], or the built-in defaults if there isn't one
+    pub fn load_default() -> Result<Self, String> {
+        match config_dir().map(|dir| dir.join("config.toml")) {
+            Some(path) if path.is_file() => Self::load(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// reads a user config file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let err = |e: String| format!("couldn't load `{}`: {e}", path.display());
+
+        let contents = fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+        let file: UserConfigFile = toml::from_str(&contents).map_err(|e| err(e.to_string()))?;
+
+        // the defaults are migrated like a preset, and only then is what they leave out filled in
+        let preset = json!({ "version": file.version.unwrap_or(VERSION), "params": file.defaults });
+        let preset = migrate(preset).map_err(err)?;
+
+        let mut params = to_map(&RingModParams::default());
+
+        if let Some(own) = preset.get("params").and_then(Value::as_object) {
+            params.extend(own.clone());
+        }
+
+        let defaults: RingModParams =
+            serde_json::from_value(Value::Object(params)).map_err(|e| err(e.to_string()))?;
+        defaults.validate().map_err(err)?;
+
+        Ok(Self {
+            preset_dir: file.preset_dir,
+            defaults,
+        })
+    }
+
+    /// the user presets in the preset directory the config points at and the factory presets
+    pub fn presets(&self) -> PresetLibrary {
+        self.preset_dir
+            .clone()
+            .or_else(|| config_dir().map(|dir| dir.join("presets")))
+            .map_or_else(PresetLibrary::factory, PresetLibrary::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::{CarrierWaveform, FrequencyRange, LfoModulation, LfoSync, Multiplier};
+
+    fn robot() -> RingModParams {
+        factory_preset("robot").unwrap().params
+    }
+
+    #[test]
+    fn v1_gets_every_knob_added_since() {
+        let preset = Preset::from_toml(
+            r#"
+            [params]
+            amount = 2.0
+            lfo_waveform = "sine"
+            rate = 1.5
+            mix = 60
+            frequency = 6.0
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(preset.version, VERSION);
+        assert_eq!(
+            preset.params,
+            RingModParams {
+                drive: 0.0,
+                amount: 2.0,
+                lfo_waveform: crate::Waveform::Sinusoidal,
+                pulse_width: 50.0,
+                lfo_slew: 0.0,
+                lfo_modulation: LfoModulation::Linear,
+                rate: 1.5,
+                sync: LfoSync::Free,
+                mix: 60,
+                frequency: 6.0,
+                range: FrequencyRange::Lo,
+                channel_phase_offset: 0.0,
+                carrier_waveform: CarrierWaveform::Sine,
+                carrier_pulse_width: 25.0,
+                multiplier: Multiplier::Ideal,
+                carrier_bleed: 0.8,
+                signal_feedthrough: 0.5,
+                nonlinearity: 0.25,
+                oversampling: 1,
+                vintage: false,
+                unit: 0,
+                drift: 8.0,
+            }
+        );
+    }
+
+    #[test]
+    fn v1_above_lo_stays_hi() {
+        let preset = Preset::from_toml(
+            r#"
+            [params]
+            amount = 2.0
+            lfo_waveform = "sine"
+            rate = 1.5
+            mix = 60
+            frequency = 156.0
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(preset.params.range, FrequencyRange::Hi);
+        assert_eq!(preset.params.frequency, 156.0);
+    }
+
+    #[test]
+    fn old_inheriting_preset_keeps_legacy_values_over_its_base() {
+        let preset = Preset::from_toml(
+            r#"
+            version = 3
+            inherits = "robot"
+
+            [params]
+            rate = 1.0
+            "#,
+        )
+        .unwrap();
+        let robot = robot();
+
+        assert_eq!(preset.inherits.as_deref(), Some("robot"));
+        assert_eq!(preset.params.rate, 1.0);
+        // inherited
+        assert_eq!(preset.params.mix, robot.mix);
+        assert_eq!(preset.params.amount, robot.amount);
+        // added after version 3, so the values from back then rather than robot's
+        assert_ne!(robot.lfo_slew, 0.0);
+        assert_eq!(preset.params.lfo_slew, 0.0);
+        assert_eq!(preset.params.lfo_modulation, LfoModulation::Linear);
+        assert_eq!(preset.params.sync, LfoSync::Free);
+        assert_eq!(preset.params.oversampling, 1);
+    }
+
+    #[test]
+    fn current_inheriting_preset_follows_its_base() {
+        let preset = Preset::from_toml(&format!(
+            "version = {VERSION}\ninherits = \"robot\"\n\n[params]\nrate = 1.0\n"
+        ))
+        .unwrap();
+
+        assert_eq!(
+            preset.params,
+            RingModParams {
+                rate: 1.0,
+                ..robot()
+            }
+        );
+    }
+
+    #[test]
+    fn v9_sweeps_linearly() {
+        let mut params = to_map(&RingModParams::default());
+        params.remove("lfo_modulation");
+        params.remove("oversampling");
+        params.insert("lfo_slew".to_string(), json!(12.0));
+
+        let preset = Preset::parse(
+            json!({ "version": 9, "params": params }),
+            &mut factory_preset,
+        )
+        .unwrap();
+
+        assert_eq!(preset.params.lfo_modulation, LfoModulation::Linear);
+        assert_eq!(preset.params.oversampling, 1);
+        assert_eq!(preset.params.lfo_slew, 12.0);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let toml = format!("version = {}\n\n[params]\n", VERSION + 1);
+
+        assert!(Preset::from_toml(&toml).is_err());
+    }
+
+    #[test]
+    fn knob_out_of_range_is_rejected() {
+        let params = RingModParams {
+            mix: 250,
+            ..RingModParams::DEFAULT
+        };
+
+        let err = Preset::from_json(&Preset::new(params, None).to_json()).unwrap_err();
+
+        assert!(err.contains("`mix`"), "{err}");
+    }
+
+    #[test]
+    fn config_defaults_are_migrated_before_filling_in() {
+        let path = std::env::temp_dir().join(format!("mf-102-config-{}.toml", std::process::id()));
+        fs::write(&path, "version = 1\n\n[defaults]\nfrequency = 6.0\n").unwrap();
+
+        let config = UserConfig::load(&path);
+        fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        assert_eq!(config.defaults.range, FrequencyRange::Lo);
+        assert_eq!(config.defaults.frequency, 6.0);
+        assert_eq!(config.defaults.lfo_modulation, LfoModulation::Linear);
+        assert_eq!(config.defaults.mix, RingModParams::DEFAULT.mix);
+    }
+}
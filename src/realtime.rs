@@ -0,0 +1,433 @@
+//! real-time processing of a live input through an audio device
+
+use crate::midi::{MidiClock, MidiControl, MidiInput, Target};
+use crate::RingModulator;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// a full duplex audio device exchanging blocks of interleaved, normalized frames
+pub trait AudioDevice {
+    /// frames per second
+    fn sample_rate(&self) -> u32;
+
+    /// samples per frame
+    fn channels(&self) -> usize;
+
+    /// frames per block
+    fn buffer_size(&self) -> usize;
+
+    /// delay the device adds between capturing and playing back a frame, in frames
+    fn latency(&self) -> usize;
+
+    /// fills `input` with the next block, returns `false` once there's no more input
+    fn read(&mut self, input: &mut [f32]) -> Result<bool, String>;
+
+    /// plays back a block
+    fn write(&mut self, output: &[f32]) -> Result<(), String>;
+}
+
+/// opens the device called `name`, `null` being a device that's always silent
+pub fn open(
+    name: &str,
+    sample_rate: u32,
+    channels: usize,
+    buffer_size: usize,
+) -> Result<Box<dyn AudioDevice>, String> {
+    if name == "null" {
+        return Ok(Box::new(NullDevice::new(
+            sample_rate,
+            channels,
+            buffer_size,
+        )));
+    }
+
+    #[cfg(feature = "alsa")]
+    {
+        AlsaDevice::open(name, sample_rate, channels, buffer_size)
+            .map(|device| Box::new(device) as Box<dyn AudioDevice>)
+    }
+
+    #[cfg(not(feature = "alsa"))]
+    Err(format!(
+        "can't open `{name}`, mf-102 was built without ALSA support, rebuild it with `--features alsa`"
+    ))
+}
+
+/// ring modulates everything `device` captures and plays it back until the input ends
+pub fn run(device: &mut dyn AudioDevice, modulator: &mut RingModulator) -> Result<(), String> {
+    let len = device.buffer_size() * device.channels();
+    let mut input = vec![0.0; len];
+    let mut output = vec![0.0; len];
+
+    while device.read(&mut input)? {
+        modulator.process_block(&input, &mut output);
+        device.write(&output)?;
+    }
+
+    Ok(())
+}
+
+/// like [`run`], turning the knobs with the MIDI that arrived before each block and following
+/// its `clock` if there is one, `on_learn` is called with every mapping learned and the target
+/// that's learned next
+pub fn run_with_midi(
+    device: &mut dyn AudioDevice,
+    modulator: &mut RingModulator,
+    midi: &mut dyn MidiInput,
+    control: &mut MidiControl,
+    mut clock: Option<&mut MidiClock>,
+    mut on_learn: impl FnMut((u8, Target), Option<Target>),
+) -> Result<(), String> {
+    let len = device.buffer_size() * device.channels();
+    let mut input = vec![0.0; len];
+    let mut output = vec![0.0; len];
+    let mut params = modulator.params().clone();
+    let mut messages = vec![];
+    // frames processed so far, when the messages of the next block arrived
+    let mut frame = 0;
+
+    while device.read(&mut input)? {
+        midi.poll(&mut messages)?;
+
+        if !messages.is_empty() {
+            for message in messages.drain(..) {
+                if let Some(clock) = &mut clock {
+                    clock.handle(message, frame, modulator);
+                }
+
+                if let Some(learned) = control.handle(message, &mut params) {
+                    on_learn(learned, control.learning());
+                }
+            }
+
+            modulator.set_params(params.clone());
+        }
+
+        modulator.process_block(&input, &mut output);
+        device.write(&output)?;
+
+        frame += device.buffer_size() as u64;
+    }
+
+    Ok(())
+}
+
+/// captures silence and discards its output, paced like a real device
+pub struct NullDevice {
+    sample_rate: u32,
+    channels: usize,
+    buffer_size: usize,
+    next_block: Instant,
+}
+
+impl NullDevice {
+    /// a silent device exchanging blocks of `buffer_size` frames
+    pub fn new(sample_rate: u32, channels: usize, buffer_size: usize) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            buffer_size,
+            next_block: Instant::now(),
+        }
+    }
+}
+
+impl AudioDevice for NullDevice {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    fn latency(&self) -> usize {
+        self.buffer_size
+    }
+
+    fn read(&mut self, input: &mut [f32]) -> Result<bool, String> {
+        thread::sleep(self.next_block.saturating_duration_since(Instant::now()));
+        self.next_block +=
+            Duration::from_secs_f64(self.buffer_size as f64 / f64::from(self.sample_rate));
+
+        input.fill(0.0);
+
+        Ok(true)
+    }
+
+    fn write(&mut self, _output: &[f32]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// captures from a signal in memory and records its output, as fast as it's processed
+pub struct FileDevice {
+    sample_rate: u32,
+    channels: usize,
+    buffer_size: usize,
+    input: Vec<f32>,
+    position: usize,
+    /// samples of the last block that came from the input rather than padding
+    valid: usize,
+    output: Vec<f32>,
+}
+
+impl FileDevice {
+    /// a device capturing the interleaved, normalized `input` in blocks of `buffer_size` frames
+    pub fn new(input: Vec<f32>, sample_rate: u32, channels: usize, buffer_size: usize) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            buffer_size,
+            output: Vec::with_capacity(input.len()),
+            input,
+            position: 0,
+            valid: 0,
+        }
+    }
+
+    /// everything that was played back
+    pub fn into_output(self) -> Vec<f32> {
+        self.output
+    }
+}
+
+impl AudioDevice for FileDevice {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    fn latency(&self) -> usize {
+        0
+    }
+
+    fn read(&mut self, input: &mut [f32]) -> Result<bool, String> {
+        let remaining = &self.input[self.position..];
+        // only whole frames are played
+        let remaining = &remaining[..remaining.len() - remaining.len() % self.channels];
+
+        if remaining.is_empty() {
+            return Ok(false);
+        }
+
+        self.valid = remaining.len().min(input.len());
+        input[..self.valid].copy_from_slice(&remaining[..self.valid]);
+        input[self.valid..].fill(0.0);
+        self.position += self.valid;
+
+        Ok(true)
+    }
+
+    fn write(&mut self, output: &[f32]) -> Result<(), String> {
+        self.output.extend_from_slice(&output[..self.valid]);
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alsa")]
+pub use alsa_device::AlsaDevice;
+
+#[cfg(feature = "alsa")]
+mod alsa_device {
+    use super::AudioDevice;
+    use alsa::pcm::{Access, Format, Frames, HwParams, PCM};
+    use alsa::{Direction, ValueOr};
+
+    /// a capture and a playback PCM of the same ALSA device
+    ///
+    /// JACK can be reached through ALSA's `jack` PCM plugin
+    pub struct AlsaDevice {
+        capture: PCM,
+        playback: PCM,
+        sample_rate: u32,
+        channels: usize,
+        buffer_size: usize,
+        latency: usize,
+    }
+
+    /// the negotiated sample rate, period size and buffer size of an opened PCM
+    struct Negotiated {
+        sample_rate: u32,
+        period_size: usize,
+        buffer_size: usize,
+    }
+
+    fn open_pcm(
+        name: &str,
+        direction: Direction,
+        sample_rate: u32,
+        channels: usize,
+        period_size: usize,
+    ) -> alsa::Result<(PCM, Negotiated)> {
+        let pcm = PCM::new(name, direction, false)?;
+
+        let negotiated = {
+            let hwp = HwParams::any(&pcm)?;
+            hwp.set_channels(channels as u32)?;
+            hwp.set_rate(sample_rate, ValueOr::Nearest)?;
+            hwp.set_format(Format::float())?;
+            hwp.set_access(Access::RWInterleaved)?;
+            hwp.set_period_size_near(period_size as Frames, ValueOr::Nearest)?;
+            hwp.set_buffer_size_near(2 * period_size as Frames)?;
+            pcm.hw_params(&hwp)?;
+
+            let hwp = pcm.hw_params_current()?;
+            Negotiated {
+                sample_rate: hwp.get_rate()?,
+                period_size: hwp.get_period_size()? as usize,
+                buffer_size: hwp.get_buffer_size()? as usize,
+            }
+        };
+
+        Ok((pcm, negotiated))
+    }
+
+    impl AlsaDevice {
+        /// opens ALSA device `name` for capture and playback with periods of `buffer_size` frames
+        pub fn open(
+            name: &str,
+            sample_rate: u32,
+            channels: usize,
+            buffer_size: usize,
+        ) -> Result<Self, String> {
+            let err = |e: alsa::Error| format!("couldn't open ALSA device `{name}`: {e}");
+
+            let (capture, input) =
+                open_pcm(name, Direction::Capture, sample_rate, channels, buffer_size)
+                    .map_err(err)?;
+            let (playback, output) = open_pcm(
+                name,
+                Direction::Playback,
+                sample_rate,
+                channels,
+                input.period_size,
+            )
+            .map_err(err)?;
+
+            if input.sample_rate != sample_rate || output.sample_rate != sample_rate {
+                return Err(format!(
+                    "ALSA device `{name}` doesn't support a sample rate of {sample_rate}Hz"
+                ));
+            }
+
+            // keep a period of silence queued so playback doesn't underrun while the first
+            // block is being captured
+            playback.prepare().map_err(err)?;
+            playback
+                .io_f32()
+                .and_then(|io| io.writei(&vec![0.0; input.period_size * channels]))
+                .map_err(err)?;
+            capture.start().map_err(err)?;
+
+            Ok(Self {
+                capture,
+                playback,
+                sample_rate,
+                channels,
+                buffer_size: input.period_size,
+                latency: input.period_size + output.buffer_size,
+            })
+        }
+    }
+
+    impl AudioDevice for AlsaDevice {
+        fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+
+        fn channels(&self) -> usize {
+            self.channels
+        }
+
+        fn buffer_size(&self) -> usize {
+            self.buffer_size
+        }
+
+        fn latency(&self) -> usize {
+            self.latency
+        }
+
+        fn read(&mut self, input: &mut [f32]) -> Result<bool, String> {
+            let io = self.capture.io_f32().map_err(|e| e.to_string())?;
+            let mut filled = 0;
+
+            while filled < input.len() {
+                match io.readi(&mut input[filled..]) {
+                    Ok(frames) => filled += frames * self.channels,
+                    // overruns are recovered from by dropping the lost input
+                    Err(e) => self
+                        .capture
+                        .try_recover(e, true)
+                        .map_err(|e| format!("capture failed: {e}"))?,
+                }
+            }
+
+            Ok(true)
+        }
+
+        fn write(&mut self, output: &[f32]) -> Result<(), String> {
+            let io = self.playback.io_f32().map_err(|e| e.to_string())?;
+            let mut written = 0;
+
+            while written < output.len() {
+                match io.writei(&output[written..]) {
+                    Ok(frames) => written += frames * self.channels,
+                    Err(e) => self
+                        .playback
+                        .try_recover(e, true)
+                        .map_err(|e| format!("playback failed: {e}"))?,
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RingModParams;
+
+    #[test]
+    fn file_device_matches_process_block() {
+        let (sample_rate, channels) = (48000, 2);
+        // a ragged number of frames, so the last block is padded
+        let input: Vec<f32> = (0..1000 * channels)
+            .map(|i| (i as f32 * 0.013).sin() * 0.8)
+            .collect();
+
+        let mut modulator = RingModulator::new(RingModParams::default(), sample_rate, channels);
+        let mut expected = vec![0.0; input.len()];
+        modulator.process_block(&input, &mut expected);
+
+        let mut device = FileDevice::new(input, sample_rate, channels, 256);
+        let mut modulator = RingModulator::new(RingModParams::default(), sample_rate, channels);
+        run(&mut device, &mut modulator).unwrap();
+
+        assert_eq!(device.into_output(), expected);
+    }
+
+    #[test]
+    fn null_device_is_silent() {
+        let mut device = NullDevice::new(48000, 2, 16);
+        let mut input = vec![1.0; 32];
+
+        assert!(device.read(&mut input).unwrap());
+        assert!(input.iter().all(|&sample| sample == 0.0));
+        device.write(&input).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+//! de-zippering of the knobs when they're turned while processing
+
+use std::str::FromStr;
+
+/// how a knob glides to a new value
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SmoothingMode {
+    /// exponential approach, `time` being the time constant
+    #[default]
+    OnePole,
+    /// constant rate ramp, `time` being how long it takes to get to the new value
+    Linear,
+}
+
+impl FromStr for SmoothingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "one-pole" => Ok(Self::OnePole),
+            "linear" => Ok(Self::Linear),
+            _ => Err(format!(
+                "unknown smoothing `{s}`, expected `one-pole` or `linear`"
+            )),
+        }
+    }
+}
+
+/// how a knob is smoothed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Smoothing {
+    /// the shape of the glide
+    pub mode: SmoothingMode,
+    /// in seconds, 0 jumps to new values instantly
+    pub time: f32,
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Self {
+            mode: SmoothingMode::OnePole,
+            time: 0.02,
+        }
+    }
+}
+
+/// glides a single value towards its target, frame by frame
+pub(crate) struct Smoother {
+    smoothing: Smoothing,
+    /// whether the value glides in the log domain, so every octave takes as long
+    log: bool,
+    /// the target as it was set, returned as is once the glide is over
+    target_value: f32,
+    /// `current` and `target` are in the log domain if `log` is set
+    current: f32,
+    target: f32,
+    /// one-pole coefficient
+    coefficient: f32,
+    /// linear ramp increment and the frames it still has to go
+    step: f32,
+    remaining: u32,
+    sample_rate: u32,
+}
+
+impl Smoother {
+    pub fn new(value: f32, log: bool, smoothing: Smoothing, sample_rate: u32) -> Self {
+        let mut smoother = Self {
+            smoothing,
+            log,
+            target_value: value,
+            current: 0.0,
+            target: 0.0,
+            coefficient: 0.0,
+            step: 0.0,
+            remaining: 0,
+            sample_rate,
+        };
+
+        smoother.configure(smoothing, sample_rate);
+        smoother.snap();
+
+        smoother
+    }
+
+    pub fn configure(&mut self, smoothing: Smoothing, sample_rate: u32) {
+        self.smoothing = smoothing;
+        self.sample_rate = sample_rate;
+
+        let frames = smoothing.time * sample_rate as f32;
+        self.coefficient = if frames > 0.0 {
+            1.0 - (-1.0 / frames).exp()
+        } else {
+            1.0
+        };
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.configure(self.smoothing, sample_rate);
+    }
+
+    /// jumps to the target, ending any glide
+    pub fn snap(&mut self) {
+        self.target = self.domain(self.target_value);
+        self.current = self.target;
+        self.remaining = 0;
+    }
+
+    pub fn set_target(&mut self, value: f32) {
+        if value == self.target_value {
+            return;
+        }
+
+        self.target_value = value;
+        self.target = self.domain(value);
+
+        if let SmoothingMode::Linear = self.smoothing.mode {
+            let frames = (self.smoothing.time * self.sample_rate as f32).round() as u32;
+
+            self.remaining = frames;
+            self.step = (self.target - self.current) / frames.max(1) as f32;
+        }
+    }
+
+    /// the value for the next frame
+    pub fn next(&mut self) -> f32 {
+        if self.current == self.target {
+            return self.target_value;
+        }
+
+        match self.smoothing.mode {
+            SmoothingMode::OnePole => {
+                self.current += (self.target - self.current) * self.coefficient;
+
+                if (self.target - self.current).abs() <= f32::EPSILON * self.target.abs().max(1.0) {
+                    self.current = self.target;
+                }
+            }
+            SmoothingMode::Linear => {
+                if self.remaining <= 1 {
+                    self.current = self.target;
+                } else {
+                    self.current += self.step;
+                }
+
+                self.remaining = self.remaining.saturating_sub(1);
+            }
+        }
+
+        if self.current == self.target {
+            self.target_value
+        } else if self.log {
+            self.current.exp()
+        } else {
+            self.current
+        }
+    }
+
+    fn domain(&self, value: f32) -> f32 {
+        if self.log {
+            value.max(f32::MIN_POSITIVE).ln()
+        } else {
+            value
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! the drift and component tolerances of an aging analog unit
+
+use crate::rng::Rng;
+
+/// seconds between the random pitches the carrier drifts through
+const CARRIER_DRIFT_PERIOD: f32 = 2.3;
+
+/// seconds between the random rates the LFO drifts through
+const LFO_DRIFT_PERIOD: f32 = 3.7;
+
+/// how far a unit's carrier frequency is off, as a ratio
+const CARRIER_TOLERANCE: f32 = 0.03;
+
+/// how far a unit's LFO rate is off, as a ratio
+const LFO_TOLERANCE: f32 = 0.08;
+
+/// how far a unit's square LFO's duty cycle is off its pulse width
+const DUTY_TOLERANCE: f32 = 0.03;
+
+/// the most a unit's oscillators skew their sine waves
+const MAX_ASYMMETRY: f32 = 0.08;
+
+/// the tolerances of a unit and the drift of its oscillators
+pub struct Vintage {
+    unit: u32,
+    /// the frequency the dial is off by
+    pub carrier_tolerance: f32,
+    /// the rate the dial is off by
+    pub lfo_tolerance: f32,
+    /// how far the carrier leans, positive rising faster than it falls
+    pub carrier_asymmetry: f32,
+    /// how far the LFO leans
+    pub lfo_asymmetry: f32,
+    /// the fraction of a cycle the square LFO is high for longer than its pulse width
+    pub duty_offset: f32,
+    /// the carrier's frequency drift
+    pub carrier_drift: Drift,
+    /// the LFO's rate drift
+    pub lfo_drift: Drift,
+}
+
+impl Vintage {
+    /// the unit numbered `unit`, the same unit always having the same tolerances and drift
+    pub fn new(unit: u32, sample_rate: u32) -> Self {
+        let mut rng = Rng::new(u64::from(unit));
+        let mut spread = |max: f32| (rng.next_f32() * 2.0 - 1.0) * max;
+
+        let carrier_tolerance = 1.0 + spread(CARRIER_TOLERANCE);
+        let lfo_tolerance = 1.0 + spread(LFO_TOLERANCE);
+        let carrier_asymmetry = spread(MAX_ASYMMETRY);
+        let lfo_asymmetry = spread(MAX_ASYMMETRY);
+        let duty_offset = spread(DUTY_TOLERANCE);
+
+        Self {
+            unit,
+            carrier_tolerance,
+            lfo_tolerance,
+            carrier_asymmetry,
+            lfo_asymmetry,
+            duty_offset,
+            carrier_drift: Drift::new(rng.next_u64(), CARRIER_DRIFT_PERIOD, sample_rate),
+            lfo_drift: Drift::new(rng.next_u64(), LFO_DRIFT_PERIOD, sample_rate),
+        }
+    }
+
+    /// the unit's number
+    pub fn unit(&self) -> u32 {
+        self.unit
+    }
+
+    /// the drift keeps its position, only its speed changes
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.carrier_drift.set_sample_rate(sample_rate);
+        self.lfo_drift.set_sample_rate(sample_rate);
+    }
+}
+
+/// `phase` in radians warped so a waveform leans by `asymmetry`, staying within 0 to 2π
+pub fn skew(phase: f32, asymmetry: f32) -> f32 {
+    phase + asymmetry * phase.sin()
+}
+
+/// a slow, smooth random walk between -1 and 1
+pub struct Drift {
+    rng: Rng,
+    period: f32,
+    from: f32,
+    to: f32,
+    /// 0 to 1 from `from` to `to`
+    position: f32,
+    increment: f32,
+}
+
+impl Drift {
+    fn new(seed: u64, period: f32, sample_rate: u32) -> Self {
+        let mut rng = Rng::new(seed);
+        let from = rng.next_f32() * 2.0 - 1.0;
+        let to = rng.next_f32() * 2.0 - 1.0;
+
+        Self {
+            rng,
+            period,
+            from,
+            to,
+            position: 0.0,
+            increment: 1.0 / (period * sample_rate as f32),
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.increment = 1.0 / (self.period * sample_rate as f32);
+    }
+
+    /// the ratio a pitch is off by, drifting by up to `cents` either way
+    pub fn next_ratio(&mut self, cents: f32) -> f32 {
+        self.position += self.increment;
+
+        if self.position >= 1.0 {
+            self.position -= 1.0;
+            self.from = self.to;
+            self.to = self.rng.next_f32() * 2.0 - 1.0;
+        }
+
+        // smoothstep, so the drift doesn't change direction abruptly
+        let t = self.position * self.position * (3.0 - 2.0 * self.position);
+        let drift = self.from + (self.to - self.from) * t;
+
+        2f32.powf(drift * cents / 1200.0)
+    }
+}
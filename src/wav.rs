@@ -0,0 +1,164 @@
+//! conversion between WAV files and the engine's normalized samples
+
+use crate::clip::{ClipMode, Clipper};
+use crate::dither::{Dither, Quantizer};
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+/// full scale of an integer sample with `bits` bits, normalized samples are divided by this
+fn full_scale(bits: u16) -> f32 {
+    (1i64 << (bits - 1)) as f32
+}
+
+/// reads a 8, 16, 24 or 32-bit integer or 32-bit float WAV file in chunks of interleaved samples
+/// normalized to -1.0..=1.0
+pub struct Reader {
+    reader: WavReader<BufReader<File>>,
+    full_scale: f32,
+}
+
+impl Reader {
+    /// opens the file at `path` and reads its header
+    pub fn open(path: impl AsRef<Path>) -> hound::Result<Self> {
+        let reader = WavReader::open(path)?;
+        let full_scale = full_scale(reader.spec().bits_per_sample);
+
+        Ok(Self { reader, full_scale })
+    }
+
+    /// the file's format
+    pub fn spec(&self) -> WavSpec {
+        self.reader.spec()
+    }
+
+    /// total number of samples in the file, across all channels
+    pub fn len(&self) -> usize {
+        self.reader.len() as usize
+    }
+
+    /// whether the file has no samples
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// fills `buf` with the next samples, returns how many were read
+    pub fn read(&mut self, buf: &mut [f32]) -> hound::Result<usize> {
+        let mut read = 0;
+
+        match self.spec().sample_format {
+            SampleFormat::Float => {
+                for (out, sample) in buf.iter_mut().zip(self.reader.samples::<f32>()) {
+                    *out = sample?;
+                    read += 1;
+                }
+            }
+            SampleFormat::Int => {
+                for (out, sample) in buf.iter_mut().zip(self.reader.samples::<i32>()) {
+                    *out = sample? as f32 / self.full_scale;
+                    read += 1;
+                }
+            }
+        }
+
+        Ok(read)
+    }
+}
+
+/// reads every sample of a WAV file at once, see [`Reader`]
+pub fn read(path: impl AsRef<Path>) -> hound::Result<(WavSpec, Vec<f32>)> {
+    let mut reader = Reader::open(path)?;
+    let mut samples = vec![0.0; reader.len()];
+    let read = reader.read(&mut samples)?;
+    samples.truncate(read);
+
+    Ok((reader.spec(), samples))
+}
+
+/// writes normalized interleaved samples in chunks in the format described by its `WavSpec`,
+/// keeping them within full scale and dithering integer output
+pub struct Writer {
+    writer: WavWriter<BufWriter<File>>,
+    clipper: Clipper,
+    quantizer: Option<Quantizer>,
+    channels: usize,
+    /// the clipper's output waiting to be written
+    buffer: Vec<f32>,
+}
+
+impl Writer {
+    /// creates the file at `path`, writing samples as `clip` and `dither` prescribe
+    pub fn create(
+        path: impl AsRef<Path>,
+        spec: WavSpec,
+        clip: ClipMode,
+        dither: Dither,
+    ) -> hound::Result<Self> {
+        let channels = usize::from(spec.channels);
+        let quantizer = match spec.sample_format {
+            SampleFormat::Float => None,
+            SampleFormat::Int => Some(Quantizer::new(spec.bits_per_sample, channels, dither)),
+        };
+
+        Ok(Self {
+            writer: WavWriter::create(path, spec)?,
+            clipper: Clipper::new(clip, channels, spec.sample_rate),
+            quantizer,
+            channels,
+            buffer: vec![],
+        })
+    }
+
+    /// writes whole frames of `samples`
+    pub fn write(&mut self, samples: &[f32]) -> hound::Result<()> {
+        self.clipper.process(samples, &mut self.buffer);
+        self.write_buffer()
+    }
+
+    /// writes what's still held back and the header,
+    /// returns the number of samples that were over full scale
+    pub fn finalize(mut self) -> hound::Result<usize> {
+        let clipped = self.clipper.finish(&mut self.buffer);
+
+        self.write_buffer()?;
+        self.writer.finalize()?;
+
+        Ok(clipped)
+    }
+
+    fn write_buffer(&mut self) -> hound::Result<()> {
+        match &mut self.quantizer {
+            None => {
+                for &sample in &self.buffer {
+                    self.writer.write_sample(sample)?;
+                }
+            }
+            Some(quantizer) => {
+                for (i, &sample) in self.buffer.iter().enumerate() {
+                    self.writer
+                        .write_sample(quantizer.quantize(i % self.channels, sample))?;
+                }
+            }
+        }
+
+        self.buffer.clear();
+
+        Ok(())
+    }
+}
+
+/// writes every sample at once, see [`Writer`]
+///
+/// returns the number of samples that were over full scale
+pub fn write(
+    path: impl AsRef<Path>,
+    spec: WavSpec,
+    samples: &[f32],
+    clip: ClipMode,
+    dither: Dither,
+) -> hound::Result<usize> {
+    let mut writer = Writer::create(path, spec, clip, dither)?;
+    writer.write(samples)?;
+    writer.finalize()
+}